@@ -0,0 +1,122 @@
+
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use yaml_rust2::Yaml;
+
+fn yaml_to_strings(yaml: &Yaml) -> Option<Vec<String>> {
+    yaml.as_vec().map(|values| {
+        values.iter().filter_map(|value| value.as_str().map(|value| value.to_string())).collect()
+    })
+}
+
+/*
+ * An API key and its permissions.
+ * Keys are defined in the config or stored in the KVS as JSON.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub key: String,
+    // Human readable owner of the key.
+    #[serde(default)]
+    pub name: String,
+    // Path prefixes the key may access. All routes are allowed if omitted.
+    #[serde(default)]
+    pub routes: Option<Vec<String>>,
+    // Chains the key may access. All chains are allowed if omitted.
+    #[serde(default)]
+    pub chains: Option<Vec<String>>,
+}
+
+impl ApiKey {
+    pub fn from_yaml(yaml: &Yaml) -> Option<Self> {
+        Some(Self {
+            key: yaml["key"].as_str()?.to_string(),
+            name: yaml["name"].as_str().unwrap_or("").to_string(),
+            routes: yaml_to_strings(&yaml["routes"]),
+            chains: yaml_to_strings(&yaml["chains"]),
+        })
+    }
+    pub fn allows(&self, chain: &str, path: &str) -> bool {
+        let chain_allowed = self.chains.as_ref().is_none_or(|chains| chains.iter().any(|c| c == chain));
+        let route_allowed = self.routes.as_ref().is_none_or(|routes| routes.iter().any(|route| path.starts_with(route.as_str())));
+        chain_allowed && route_allowed
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuthConfig {
+    // Require an API key for every request except the public routes.
+    pub enabled: bool,
+    pub keys: Vec<ApiKey>,
+    // Path prefixes accessible without an API key.
+    pub public_routes: Vec<String>,
+}
+
+impl AuthConfig {
+    // Load from the `auth` section of a server config.
+    pub fn from_yaml(yaml: &Yaml) -> Self {
+        let keys = yaml["keys"].as_vec().map(|keys| {
+            keys.iter().filter_map(ApiKey::from_yaml).collect()
+        });
+        Self {
+            enabled: yaml["enabled"].as_bool().unwrap_or(false),
+            keys: keys.unwrap_or_default(),
+            public_routes: yaml_to_strings(&yaml["publicRoutes"]).unwrap_or_default(),
+        }
+    }
+    pub fn get_key(&self, key: &str) -> Option<&ApiKey> {
+        self.keys.iter().find(|api_key| api_key.key == key)
+    }
+    pub fn is_public(&self, path: &str) -> bool {
+        self.public_routes.iter().any(|route| path.starts_with(route.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use yaml_rust2::YamlLoader;
+
+    #[test]
+    fn allows() {
+        let api_key = ApiKey {
+            key: "secret".to_string(),
+            name: "partner".to_string(),
+            routes: Some(vec!["/rest/tx/".to_string()]),
+            chains: Some(vec!["BTC".to_string()]),
+        };
+        assert!(api_key.allows("BTC", "/rest/tx/00.bin"));
+        assert!(!api_key.allows("BTC", "/rest/block/00.bin"));
+        assert!(!api_key.allows("TBTC", "/rest/tx/00.bin"));
+        let api_key = ApiKey {
+            routes: None,
+            chains: None,
+            ..api_key
+        };
+        assert!(api_key.allows("TBTC", "/rest/block/00.bin"));
+    }
+
+    #[test]
+    fn from_yaml() {
+        let yaml = YamlLoader::load_from_str("
+enabled: true
+keys:
+  - key: secret
+    name: partner
+    routes: [/rest/tx/]
+  - name: no key
+publicRoutes: [/health]
+").unwrap();
+        let config = AuthConfig::from_yaml(&yaml[0]);
+        assert!(config.enabled);
+        assert_eq!(config.keys.len(), 1);
+        assert_eq!(config.get_key("secret").unwrap().routes, Some(vec!["/rest/tx/".to_string()]));
+        assert_eq!(config.get_key("secret").unwrap().chains, None);
+        assert!(config.is_public("/health"));
+        assert!(!config.is_public("/rest/tx/00.bin"));
+    }
+
+}
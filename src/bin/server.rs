@@ -1,11 +1,25 @@
 
+use bitcoin::Network;
+
 use bitcoin_rest_mirror::{
+    init_logging,
     load_config,
     client::{
         RedisClientPool,
         Client,
     },
-    server::start_server,
+    electrum::{
+        ElectrumConfig,
+        start_electrum_server,
+    },
+    metrics::{
+        InstrumentedKVS,
+        start_metrics_server,
+    },
+    server::{
+        ServerConfig,
+        start_server,
+    },
 };
 
 #[tokio::main]
@@ -19,15 +33,38 @@ async fn main() {
     let chain = &args[1];
     // Load config.
     let config = load_config();
+    init_logging(&config);
     let chain_config = &config["chains"][chain.as_str()];
     // Initialize Redis connection.
     let redis_url = config["redisUrl"].as_str().unwrap();
     // Initialize client.
     let redis_client = RedisClientPool::new(redis_url);
-    let client = Client::new(redis_client, chain.clone(), None);
+    let network = chain_config["network"].as_str().unwrap_or("bitcoin").parse::<Network>().expect("Invalid network");
+    let client = Client::new(InstrumentedKVS::new(redis_client), chain.clone(), None)
+        .set_network(network)
+        .set_op_return_index(chain_config["indexes"]["opReturn"].as_bool().unwrap_or(false))
+        .set_mempool(chain_config["mempool"].as_bool().unwrap_or(false))
+        ;
+    // Serve the Electrum protocol.
+    if let Some(electrum_port) = chain_config["electrum"]["port"].as_i64() {
+        let electrum_host = chain_config["electrum"]["host"].as_str().unwrap_or("localhost").to_string();
+        let electrum_config = ElectrumConfig::from_yaml(&chain_config["electrum"]);
+        let client = client.clone();
+        tokio::spawn(async move {
+            start_electrum_server(client, electrum_port as u16, &electrum_host, electrum_config).await;
+        });
+    }
+    // Serve metrics.
+    if let Some(metrics_port) = chain_config["server"]["metricsPort"].as_i64() {
+        let metrics_host = chain_config["server"]["metricsHost"].as_str().unwrap_or("localhost").to_string();
+        tokio::spawn(async move {
+            start_metrics_server(metrics_port as u16, &metrics_host).await;
+        });
+    }
     // Initialize server.
     let port = chain_config["server"]["port"].as_i64().unwrap_or(8000);
     let host = chain_config["server"]["host"].as_str().unwrap_or("localhost");
-    start_server(client, port as u16, host).await;
+    let server_config = ServerConfig::from_yaml(&chain_config["server"]);
+    start_server(client, port as u16, host, server_config).await;
 }
 
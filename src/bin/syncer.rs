@@ -10,13 +10,23 @@ use std::thread::{
     sleep,
     available_parallelism,
 };
-use std::collections::HashMap;
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use futures::{
+    StreamExt,
+    stream,
+};
 use num_format::{
     Locale,
     ToFormattedString,
 };
 
+use bitcoin::Network;
+
 use bitcoin_rest_mirror::{
+    init_logging,
     load_config,
     blk_reader::BlkReader,
     block_downloader::BitcoinRest,
@@ -24,10 +34,18 @@ use bitcoin_rest_mirror::{
         RedisClientPool,
         Client,
     },
+    metrics::{
+        InstrumentedKVS,
+        REORGS,
+        SYNC_BLOCKS_PER_SECOND,
+        SYNC_HEIGHT,
+        start_metrics_server,
+    },
 };
 
 use bitcoin_rest_mirror::block_downloader::BlockDownloader;
 
+#[tracing::instrument(skip_all)]
 async fn sync_single(downloader: &mut BlockDownloader, client: &Client) -> u32 {
     let next_block_height = client.get_next_block_height();
     downloader.run(next_block_height).await.unwrap();
@@ -39,12 +57,18 @@ async fn sync_single(downloader: &mut BlockDownloader, client: &Client) -> u32 {
             break;
         }
         let (height, block) = block.unwrap();
+        // The block does not extend the synced chain.
+        if height > 0 && client.get_block_hash_by_height(height - 1).is_some_and(|prev_block_hash| prev_block_hash[..] != block[4..36]) {
+            REORGS.inc();
+            tracing::warn!(height, "Reorg detected.");
+        }
         client.add_block(height, block.into(), Some(true));
         blocks_processed += 1;
     }
     blocks_processed
 }
 
+#[tracing::instrument(skip_all)]
 async fn sync_multi(downloader: &mut BlockDownloader, client: &Client) -> u32 {
     let next_block_height = client.get_next_block_height();
     downloader.run(next_block_height).await.unwrap();
@@ -53,7 +77,7 @@ async fn sync_multi(downloader: &mut BlockDownloader, client: &Client) -> u32 {
     // Launch threads.
     let concurrency = available_parallelism().unwrap().get();
     let (tx, mut rx) = tokio::sync::mpsc::channel(5 * concurrency);
-    println!("Starting {} threads...", concurrency);
+    tracing::info!(concurrency, "Starting threads...");
     for _ in 0..concurrency {
         let mut downloader = downloader.clone();
         let client = client.clone();
@@ -87,6 +111,7 @@ async fn sync_multi(downloader: &mut BlockDownloader, client: &Client) -> u32 {
         let processed_new_block = loop {
             match processed_blocks.read().unwrap().get(&height) {
                 Some(true) => {
+                    client.index_block_height(height);
                     client.set_next_block_height(height + 1);
                     blocks_processed += 1;
                     break true;
@@ -107,6 +132,7 @@ async fn sync_multi(downloader: &mut BlockDownloader, client: &Client) -> u32 {
     blocks_processed
 }
 
+#[tracing::instrument(skip_all)]
 async fn sync_initial(blk_reader: &mut BlkReader, client: &Client) -> u32 {
     blk_reader.run_threads(4).await;
     let mut blocks_processed = 0;
@@ -114,7 +140,7 @@ async fn sync_initial(blk_reader: &mut BlkReader, client: &Client) -> u32 {
     // Launch threads.
     let concurrency = available_parallelism().unwrap().get();
     let (tx, mut rx) = tokio::sync::mpsc::channel(5 * concurrency);
-    println!("Starting {} threads...", concurrency);
+    tracing::info!(concurrency, "Starting threads...");
     for _ in 0..concurrency {
         let mut blk_reader = blk_reader.clone();
         let client = client.clone();
@@ -148,6 +174,7 @@ async fn sync_initial(blk_reader: &mut BlkReader, client: &Client) -> u32 {
         let processed_new_block = loop {
             match processed_blocks.read().unwrap().get(&height) {
                 Some(true) => {
+                    client.index_block_height(height);
                     client.set_next_block_height(height + 1);
                     blocks_processed += 1;
                     break true;
@@ -168,6 +195,75 @@ async fn sync_initial(blk_reader: &mut BlkReader, client: &Client) -> u32 {
     blocks_processed
 }
 
+const MEMPOOL_FETCH_CONCURRENCY: usize = 16;
+
+#[tracing::instrument(skip_all)]
+async fn sync_mempool(bitcoin_rest: &BitcoinRest, client: &Client) -> usize {
+    let contents = match bitcoin_rest.get_mempool_contents().await {
+        Ok(contents) => contents,
+        Err(_) => return 0,
+    };
+    let info = match bitcoin_rest.get_mempool_info().await {
+        Ok(info) => info,
+        Err(_) => return 0,
+    };
+    let contents = match contents.as_object() {
+        Some(contents) => contents,
+        None => return 0,
+    };
+    let old_tx_hashes = client.get_mempool_txids().into_iter().collect::<HashSet<[u8; 32]>>();
+    let mut tx_hashes = Vec::new();
+    let mut new_entries = Vec::new();
+    for (txid, entry) in contents.iter() {
+        let mut tx_hash: [u8; 32] = match hex::decode(txid).ok().and_then(|tx_hash| tx_hash.try_into().ok()) {
+            Some(tx_hash) => tx_hash,
+            None => continue,
+        };
+        tx_hash.reverse();
+        if old_tx_hashes.contains(&tx_hash) {
+            tx_hashes.push(tx_hash);
+        } else {
+            new_entries.push((tx_hash, entry));
+        }
+    }
+    // Fetch new transactions.
+    let new_txs = stream::iter(new_entries)
+        .map(|(tx_hash, entry)| async move {
+            (tx_hash, entry, bitcoin_rest.get_transaction(tx_hash).await.ok())
+        })
+        .buffer_unordered(MEMPOOL_FETCH_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    for (tx_hash, entry, tx) in new_txs {
+        // The transaction may have been confirmed or evicted in the meantime.
+        if let Some(tx) = tx {
+            client.set_mempool_transaction(&tx_hash, &tx, entry);
+            tx_hashes.push(tx_hash);
+        }
+    }
+    // Evict transactions which left the upstream mempool.
+    let current_tx_hashes = tx_hashes.iter().cloned().collect::<HashSet<[u8; 32]>>();
+    for tx_hash in old_tx_hashes.difference(&current_tx_hashes) {
+        client.remove_mempool_transaction(tx_hash);
+    }
+    client.set_mempool_txids(&tx_hashes);
+    client.set_mempool_info(&info);
+    tx_hashes.len()
+}
+
+async fn update_upstream_block_height(bitcoin_rest: &BitcoinRest, client: &Client) {
+    let chaininfo = match bitcoin_rest.get_chaininfo().await {
+        Ok(chaininfo) => chaininfo,
+        Err(e) => {
+            tracing::warn!(error = ?e, "Failed to fetch the chain info.");
+            return;
+        },
+    };
+    if let Some(blocks) = chaininfo["blocks"].as_u64() {
+        client.set_upstream_block_height(blocks as u32);
+    }
+}
+
 #[tokio::main]
 async fn main() {
     // Load chain.
@@ -179,19 +275,55 @@ async fn main() {
     let chain = &args[1];
     // Load config.
     let config = load_config();
+    init_logging(&config);
     let chain_config = &config["chains"][chain.as_str()];
     // Initialize Redis connection.
     let redis_url = config["redisUrl"].as_str().unwrap();
     // Initialize client.
     let redis_client = RedisClientPool::new(redis_url);
-    let client = Client::new(redis_client, chain.clone(), None);
+    let network = chain_config["network"].as_str().unwrap_or("bitcoin").parse::<Network>().expect("Invalid network");
+    let client = Client::new(InstrumentedKVS::new(redis_client), chain.clone(), None)
+        .set_network(network)
+        .set_op_return_index(chain_config["indexes"]["opReturn"].as_bool().unwrap_or(false))
+        .set_mempool(chain_config["mempool"].as_bool().unwrap_or(false))
+        ;
     // Initialize block downloader.
     let concurrency = config["downloader"]["concurrency"].as_i64().unwrap_or(4) as usize;
     let mut downloader = BlockDownloader::new(Some(chain_config["restUrl"].as_str().expect("restUrl not set").to_string()))
         .set_concurrency(concurrency)
         ;
+    // Initialize BitcoinRest.
+    let bitcoin_rest = BitcoinRest::new(Some(chain_config["restUrl"].as_str().unwrap().to_string()));
+    update_upstream_block_height(&bitcoin_rest, &client).await;
+    // Index heights added by older versions.
+    client.index_missing_block_heights();
+    {
+        let client = client.clone();
+        tokio::task::spawn_blocking(move || client.index_missing_transaction_blocks());
+    }
     // Fetch next block height.
     let next_block_height = client.get_next_block_height();
+    // Serve metrics.
+    if let Some(metrics_port) = chain_config["syncer"]["metricsPort"].as_i64() {
+        let metrics_host = chain_config["syncer"]["metricsHost"].as_str().unwrap_or("localhost").to_string();
+        tokio::spawn(async move {
+            start_metrics_server(metrics_port as u16, &metrics_host).await;
+        });
+    }
+    // Update sync metrics.
+    {
+        let client = client.clone();
+        tokio::spawn(async move {
+            let mut last_next_block_height = client.get_next_block_height();
+            loop {
+                tokio::time::sleep(Duration::from_millis(1000)).await;
+                let next_block_height = client.get_next_block_height();
+                SYNC_HEIGHT.set(next_block_height as i64 - 1);
+                SYNC_BLOCKS_PER_SECOND.set(next_block_height.saturating_sub(last_next_block_height) as f64);
+                last_next_block_height = next_block_height;
+            }
+        });
+    }
     // Print stats.
     let reporter_thread = {
         let client = client.clone();
@@ -201,7 +333,9 @@ async fn main() {
                 tokio::time::sleep(Duration::from_millis(1000)).await;
                 let current_height = client.get_next_block_height() as i32;
                 let processed_blocks = current_height - last_block_height;
-                println!(
+                tracing::info!(
+                    height = current_height,
+                    blocks_per_second = processed_blocks,
                     "Processing: #{}, Blocks per second: {}",
                     current_height.to_formatted_string(&Locale::en),
                     processed_blocks.to_formatted_string(&Locale::en),
@@ -215,31 +349,40 @@ async fn main() {
     let blocks_processed = if next_block_height == 0 {
         // Initialize blk_reader.
         let blocks_dir = chain_config["blocksDir"].as_str().expect("blocksDir not set").to_string();
-        println!("Reading blocks from: {}", blocks_dir);
-        // Initialize BitcoinRest.
-        let bitcoin_rest = BitcoinRest::new(Some(chain_config["restUrl"].as_str().unwrap().to_string()));
+        tracing::info!(%blocks_dir, "Reading blocks from blk files.");
         let mut blk_reader = BlkReader::new(blocks_dir);
         blk_reader.init(&bitcoin_rest, 0).await;
-        println!("Starting initial sync...");
+        tracing::info!("Starting initial sync...");
         sync_initial(&mut blk_reader, &client).await
     } else {
-        println!("Starting multi-threaded sync...");
+        tracing::info!("Starting multi-threaded sync...");
         sync_multi(&mut downloader, &client).await
     };
     // Stop reporter thread.
     reporter_thread.abort();
-    println!(
+    tracing::info!(
+        blocks = blocks_processed,
         "First sync completed: synced {} blocks.",
         blocks_processed.to_formatted_string(&Locale::en),
     );
+    // Compute the fee statistics skipped by the parallel sync.
+    {
+        let client = client.clone();
+        tokio::task::spawn_blocking(move || client.index_missing_block_stats());
+    }
     // Start sync loop.
     loop {
         sleep(Duration::from_millis(1000));
+        update_upstream_block_height(&bitcoin_rest, &client).await;
         let blocks_processed = sync_single(&mut downloader, &client).await;
+        if client.is_mempool_enabled() {
+            sync_mempool(&bitcoin_rest, &client).await;
+        }
         if blocks_processed == 0 {
             continue;
         }
-        println!(
+        tracing::info!(
+            blocks = blocks_processed,
             "Synced {} blocks.",
             blocks_processed.to_formatted_string(&Locale::en),
         );
@@ -19,6 +19,7 @@ use tokio::time::{
 use crate::{
     block_to_block_hash,
     block_downloader::BitcoinRest,
+    metrics::BLK_READER_BUFFERED_BLOCKS,
 };
 
 #[derive(Clone)]
@@ -61,13 +62,12 @@ impl BlkReader {
     pub async fn init(&self, bitcoin_rest: &BitcoinRest, starting_height: u32) {
         // Get starting block hash.
         let start_block_hash = bitcoin_rest.get_blockhashbyheight(starting_height).await.unwrap();
-        println!("Starting block hash: {}", hex::encode(start_block_hash));
+        tracing::info!(start_block_hash = hex::encode(start_block_hash), "Fetching all block headers...");
         // Download all block headers.
-        println!("Fetching all block headers...");
         let start_time = SystemTime::now();
         let headers = bitcoin_rest.get_all_headers(start_block_hash, None).await.unwrap();
         let blocks_len = headers.len();
-        println!("Fetched {} block headers in {}ms.", blocks_len, start_time.elapsed().unwrap().as_millis());
+        tracing::info!(count = blocks_len, elapsed_ms = start_time.elapsed().unwrap().as_millis() as u64, "Fetched block headers.");
         // Convert to block_height_by_hash.
         for (offset, header) in headers.iter().enumerate() {
             let block_hash = block_to_block_hash(header);
@@ -91,7 +91,7 @@ impl BlkReader {
     }
     fn read_file(&mut self, index: u32) -> Result<u32, ()> {
         let path = format!("{}/blk{:05}.dat", self.blocks_dir, index);
-        //println!("Reading: {}", path);
+        tracing::debug!(%path, "Reading blk file.");
         let file = File::open(&path);
         if file.is_err() {
             return Err(());
@@ -122,12 +122,14 @@ impl BlkReader {
             let block_hash = block_to_block_hash(&block_vec);
             let block_height = self.data.read().unwrap().block_height_by_hash.get(&block_hash).cloned();
             if block_height.is_none() {
-                //println!("Block height not found for hash: {}", hex::encode(block_hash));
+                tracing::trace!(block_hash = hex::encode(block_hash), "Block height not found.");
                 continue;
             }
             let block_height = block_height.unwrap();
             // Save blcok.
-            self.data.write().unwrap().blocks.insert(block_height, Bytes::from(block_vec));
+            let mut data = self.data.write().unwrap();
+            data.blocks.insert(block_height, Bytes::from(block_vec));
+            BLK_READER_BUFFERED_BLOCKS.set(data.blocks.len() as i64);
         }
     }
     pub fn read_next_file(&mut self) -> Result<u32, ()> {
@@ -176,6 +178,7 @@ impl BlkReader {
         if let Some(block) = data.blocks.remove(&next_height) {
             let height = data.next_height;
             data.next_height += 1;
+            BLK_READER_BUFFERED_BLOCKS.set(data.blocks.len() as i64);
             return Some((height, block));
         }
         None
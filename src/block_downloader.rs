@@ -20,6 +20,20 @@ use reqwest::{
 };
 use bitcoin_hashes::Sha256d;
 
+use crate::metrics::{
+    DOWNLOAD_QUEUE_DEPTH,
+    FETCH_RETRIES,
+};
+
+// Error of a JSON request, which may also fail after a successful response.
+#[derive(Debug)]
+pub enum FetchJsonError {
+    // The response status is not 200.
+    Status(Response),
+    Body(reqwest::Error),
+    Json(serde_json::Error),
+}
+
 #[derive(Clone)]
 pub struct BitcoinRest {
     client: reqwest::Client,
@@ -50,7 +64,8 @@ impl BitcoinRest {
                     return response;
                 },
                 Err(_) => {
-                    println!("Fetch timeouted for: {}.", url);
+                    tracing::warn!(%url, "Fetch timeouted, retrying.");
+                    FETCH_RETRIES.inc();
                     sleep(Duration::from_millis(1000)).await;
                 },
             };
@@ -73,11 +88,34 @@ impl BitcoinRest {
         let bytes = response.bytes().await.unwrap();
         Ok(bytes)
     }
+    pub async fn fetch_json(&self, path: &[&str], query: Option<&str>) -> Result<serde_json::Value, FetchJsonError> {
+        let response = self.fetch(path, "json", query).await;
+        if response.status() != StatusCode::OK {
+            return Err(FetchJsonError::Status(response));
+        }
+        let bytes = response.bytes().await.map_err(FetchJsonError::Body)?;
+        let json = serde_json::from_slice(&bytes).map_err(FetchJsonError::Json)?;
+        Ok(json)
+    }
+    pub async fn get_chaininfo(&self) -> Result<serde_json::Value, FetchJsonError> {
+        self.fetch_json(&["chaininfo"], None).await
+    }
     pub async fn get_block(&self, mut hash: [u8; 32]) -> Result<Bytes, Response> {
         hash.reverse();
         let block = self.fetch_bin(&["block", &hex::encode(hash)], None).await?;
         Ok(block)
     }
+    pub async fn get_transaction(&self, mut hash: [u8; 32]) -> Result<Bytes, Response> {
+        hash.reverse();
+        let tx = self.fetch_bin(&["tx", &hex::encode(hash)], None).await?;
+        Ok(tx)
+    }
+    pub async fn get_mempool_info(&self) -> Result<serde_json::Value, FetchJsonError> {
+        self.fetch_json(&["mempool", "info"], None).await
+    }
+    pub async fn get_mempool_contents(&self) -> Result<serde_json::Value, FetchJsonError> {
+        self.fetch_json(&["mempool", "contents"], None).await
+    }
     pub async fn get_blockhashbyheight(&self, height: u32) -> Result<[u8; 32], Response> {
         let block_hash = self.fetch_bin(&["blockhashbyheight", &height.to_string()], None).await?;
         if block_hash.len() != 32 {
@@ -185,6 +223,7 @@ impl BlockDownloader {
         if data.blocks.contains_key(&current_height) {
             let block = data.blocks.remove(&current_height).unwrap();
             data.current_height += 1;
+            DOWNLOAD_QUEUE_DEPTH.set(data.blocks.len() as i64);
             return Some((current_height, block));
         }
         None
@@ -211,17 +250,17 @@ impl BlockDownloader {
         }
         let first_block_hash = first_block_hash.unwrap();
         // Fetch all headers.
-        println!("Fetching all block headers...");
+        tracing::info!(start_height, "Fetching all block headers...");
         let start_time = SystemTime::now();
         let headers = self.bitcoin_rest.get_all_headers(first_block_hash, None).await?;
         let blocks_len = headers.len();
-        println!("Fetched {} block headers in {}ms.", blocks_len, start_time.elapsed().unwrap().as_millis());
+        tracing::info!(count = blocks_len, elapsed_ms = start_time.elapsed().unwrap().as_millis() as u64, "Fetched block headers.");
         let start_time = SystemTime::now();
         let block_hashes = headers.par_iter().map(|header| Sha256d::hash(header).to_byte_array()).collect::<Vec<[u8; 32]>>();
         let block_hashes = Arc::new(RwLock::new(block_hashes));
-        println!("Computed block hashes in {}ms.", start_time.elapsed().unwrap().as_millis());
+        tracing::info!(elapsed_ms = start_time.elapsed().unwrap().as_millis() as u64, "Computed block hashes.");
         self.data.write().unwrap().max_height = start_height + blocks_len as u32 - 1;
-        println!("Fetching blocks with {} threads...", self.concurrency);
+        tracing::info!(concurrency = self.concurrency, "Fetching blocks...");
         self.data.write().unwrap().next_height = start_height;
         for _ in 0..self.concurrency {
             let downloader = self.clone();
@@ -251,10 +290,12 @@ impl BlockDownloader {
                     let block_hash = block_hashes.read().unwrap()[(height - start_height) as usize];
                     let block = downloader.bitcoin_rest.get_block(block_hash).await;
                     if block.is_err() {
-                        println!("Failed to fetch block {}.", height);
+                        tracing::error!(height, "Failed to fetch block.");
                     }
                     let block = block.unwrap();
-                    downloader.data.write().unwrap().blocks.insert(height, block);
+                    let mut data = downloader.data.write().unwrap();
+                    data.blocks.insert(height, block);
+                    DOWNLOAD_QUEUE_DEPTH.set(data.blocks.len() as i64);
                 }
             });
         }
@@ -0,0 +1,264 @@
+
+use std::collections::HashMap;
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use bitcoin::{
+    Amount,
+    OutPoint,
+    TxOut,
+    block::Block,
+};
+
+const HALVING_INTERVAL: u32 = 210_000;
+const WITNESS_SCALE_FACTOR: i64 = 4;
+
+/*
+ * Per-block statistics compatible with the `getblockstats` RPC of Bitcoin Core.
+ * Amounts are in satoshis and fee rates are in sat/vB.
+ *
+ * Fee related fields are `None` when some of the spent outputs were not available
+ * at the time the block was registered (e.g. blocks registered out of order during the initial sync).
+ * They are filled in once the block is indexed in height order.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockStats {
+    pub blockhash: String,
+    pub height: u32,
+    pub time: u32,
+    pub txs: usize,
+    pub ins: usize,
+    pub outs: usize,
+    pub total_size: usize,
+    pub total_weight: usize,
+    pub avgtxsize: usize,
+    pub mintxsize: usize,
+    pub maxtxsize: usize,
+    pub total_out: u64,
+    pub subsidy: u64,
+    pub swtxs: usize,
+    pub swtotal_size: usize,
+    pub swtotal_weight: usize,
+    pub taproot_outs: usize,
+    pub taproot_ins: Option<usize>,
+    pub totalfee: Option<i64>,
+    pub avgfee: Option<i64>,
+    pub minfee: Option<i64>,
+    pub maxfee: Option<i64>,
+    pub medianfee: Option<i64>,
+    pub avgfeerate: Option<i64>,
+    pub minfeerate: Option<i64>,
+    pub maxfeerate: Option<i64>,
+    pub feerate_percentiles: Option<[i64; 5]>,
+}
+
+fn block_subsidy(height: u32) -> u64 {
+    let halvings = height / HALVING_INTERVAL;
+    if halvings >= 64 {
+        return 0;
+    }
+    Amount::from_int_btc(50).to_sat() >> halvings
+}
+
+fn truncated_median(values: &mut [i64]) -> i64 {
+    if values.is_empty() {
+        return 0;
+    }
+    values.sort();
+    let size = values.len();
+    if size.is_multiple_of(2) {
+        (values[size / 2 - 1] + values[size / 2]) / 2
+    } else {
+        values[size / 2]
+    }
+}
+
+// Compute the 10th, 25th, 50th, 75th and 90th fee rate percentiles weighted by transaction weight.
+fn percentiles_by_weight(mut scores: Vec<(i64, usize)>, total_weight: usize) -> [i64; 5] {
+    let mut result = [0i64; 5];
+    if scores.is_empty() {
+        return result;
+    }
+    scores.sort();
+    let total_weight = total_weight as f64;
+    let weights = [
+        total_weight / 10.0,
+        total_weight / 4.0,
+        total_weight / 2.0,
+        (total_weight * 3.0) / 4.0,
+        (total_weight * 9.0) / 10.0,
+    ];
+    let mut next_percentile_index = 0;
+    let mut cumulative_weight = 0;
+    for (feerate, weight) in scores.iter() {
+        cumulative_weight += weight;
+        while next_percentile_index < weights.len() && cumulative_weight as f64 >= weights[next_percentile_index] {
+            result[next_percentile_index] = *feerate;
+            next_percentile_index += 1;
+        }
+    }
+    // Fill any remaining percentiles with the last value.
+    for percentile in result.iter_mut().skip(next_percentile_index) {
+        *percentile = scores.last().unwrap().0;
+    }
+    result
+}
+
+impl BlockStats {
+    /*
+     * @param get_prevout Resolves an outpoint spent by the block. Outputs created in the block itself
+     *                    are resolved internally.
+     */
+    pub fn compute<F>(height: u32, block: &Block, mut get_prevout: F) -> Self
+    where
+        F: FnMut(&OutPoint) -> Option<TxOut>,
+    {
+        let txids = block.txdata.iter().enumerate().map(|(index, tx)| (tx.compute_txid(), index)).collect::<HashMap<_, _>>();
+        let mut ins = 0;
+        let mut outs = 0;
+        let mut total_size = 0;
+        let mut total_weight = 0;
+        let mut mintxsize = usize::MAX;
+        let mut maxtxsize = 0;
+        let mut total_out = 0;
+        let mut swtxs = 0;
+        let mut swtotal_size = 0;
+        let mut swtotal_weight = 0;
+        let mut taproot_outs = 0;
+        let mut taproot_ins = 0;
+        let mut fees = Vec::new();
+        let mut feerates = Vec::new();
+        let mut prevouts_available = true;
+        for tx in block.txdata.iter() {
+            outs += tx.output.len();
+            taproot_outs += tx.output.iter().filter(|output| output.script_pubkey.is_p2tr()).count();
+            let tx_total_out = tx.output.iter().map(|output| output.value.to_sat()).sum::<u64>();
+            if tx.is_coinbase() {
+                continue;
+            }
+            ins += tx.input.len();
+            total_out += tx_total_out;
+            let tx_size = tx.total_size();
+            mintxsize = mintxsize.min(tx_size);
+            maxtxsize = maxtxsize.max(tx_size);
+            total_size += tx_size;
+            let weight = tx.weight().to_wu() as usize;
+            total_weight += weight;
+            if tx.input.iter().any(|input| !input.witness.is_empty()) {
+                swtxs += 1;
+                swtotal_size += tx_size;
+                swtotal_weight += weight;
+            }
+            if !prevouts_available {
+                continue;
+            }
+            let mut tx_total_in = 0;
+            for input in tx.input.iter() {
+                let prevout = match txids.get(&input.previous_output.txid) {
+                    Some(index) => block.txdata[*index].output.get(input.previous_output.vout as usize).cloned(),
+                    None => get_prevout(&input.previous_output),
+                };
+                match prevout {
+                    Some(prevout) => {
+                        if prevout.script_pubkey.is_p2tr() {
+                            taproot_ins += 1;
+                        }
+                        tx_total_in += prevout.value.to_sat();
+                    },
+                    None => {
+                        prevouts_available = false;
+                        break;
+                    },
+                }
+            }
+            let fee = tx_total_in as i64 - tx_total_out as i64;
+            fees.push(fee);
+            feerates.push((if weight > 0 { fee * WITNESS_SCALE_FACTOR / weight as i64 } else { 0 }, weight));
+        }
+        let txs = block.txdata.len();
+        let (totalfee, avgfee, minfee, maxfee, medianfee, avgfeerate, minfeerate, maxfeerate, feerate_percentiles) = if prevouts_available {
+            let totalfee = fees.iter().sum::<i64>();
+            (
+                Some(totalfee),
+                Some(if txs > 1 { totalfee / (txs as i64 - 1) } else { 0 }),
+                Some(fees.iter().min().cloned().unwrap_or(0)),
+                Some(fees.iter().max().cloned().unwrap_or(0)),
+                Some(truncated_median(&mut fees)),
+                Some(if total_weight > 0 { totalfee * WITNESS_SCALE_FACTOR / total_weight as i64 } else { 0 }),
+                Some(feerates.iter().map(|(feerate, _)| *feerate).min().unwrap_or(0)),
+                Some(feerates.iter().map(|(feerate, _)| *feerate).max().unwrap_or(0)),
+                Some(percentiles_by_weight(feerates, total_weight)),
+            )
+        } else {
+            (None, None, None, None, None, None, None, None, None)
+        };
+        Self {
+            blockhash: block.block_hash().to_string(),
+            height,
+            time: block.header.time,
+            txs,
+            ins,
+            outs,
+            total_size,
+            total_weight,
+            avgtxsize: if txs > 1 { total_size / (txs - 1) } else { 0 },
+            mintxsize: if mintxsize == usize::MAX { 0 } else { mintxsize },
+            maxtxsize,
+            total_out,
+            subsidy: block_subsidy(height),
+            swtxs,
+            swtotal_size,
+            swtotal_weight,
+            taproot_outs,
+            taproot_ins: if prevouts_available { Some(taproot_ins) } else { None },
+            totalfee,
+            avgfee,
+            minfee,
+            maxfee,
+            medianfee,
+            avgfeerate,
+            minfeerate,
+            maxfeerate,
+            feerate_percentiles,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::consensus::Decodable;
+
+    #[test]
+    fn subsidy() {
+        assert_eq!(block_subsidy(0), 5_000_000_000);
+        assert_eq!(block_subsidy(209_999), 5_000_000_000);
+        assert_eq!(block_subsidy(210_000), 2_500_000_000);
+        assert_eq!(block_subsidy(840_000), 312_500_000);
+        assert_eq!(block_subsidy(64 * 210_000), 0);
+    }
+
+    #[test]
+    fn percentiles() {
+        assert_eq!(percentiles_by_weight(vec![], 0), [0; 5]);
+        assert_eq!(percentiles_by_weight(vec![(5, 100), (1, 100), (3, 200)], 400), [1, 1, 3, 3, 5]);
+    }
+
+    #[test]
+    fn genesis() {
+        let blocks = crate::client::tests::load_blocks();
+        let block = Block::consensus_decode(&mut blocks[0].as_slice()).unwrap();
+        let stats = BlockStats::compute(0, &block, |_| None);
+        assert_eq!(stats.blockhash, "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+        assert_eq!(stats.txs, 1);
+        assert_eq!(stats.ins, 0);
+        assert_eq!(stats.outs, 1);
+        assert_eq!(stats.total_size, 0);
+        assert_eq!(stats.subsidy, 5_000_000_000);
+        assert_eq!(stats.totalfee, Some(0));
+        assert_eq!(stats.feerate_percentiles, Some([0; 5]));
+    }
+
+}
@@ -9,7 +9,17 @@ use std::sync::{
 use redis::Commands;
 use bitcoin::{
     VarInt,
-    block::Block,
+    BlockHash,
+    Network,
+    OutPoint,
+    TxOut,
+    Transaction,
+    block::{
+        Block,
+        Header,
+    },
+    pow::Work,
+    hashes::Hash,
     consensus::{
         Encodable,
         Decodable,
@@ -19,11 +29,40 @@ use bitcoin::{
 
 use crate::{
     Binary,
+    auth::ApiKey,
+    block_stats::BlockStats,
+    json::ChainPosition,
+    miner_info::MinerInfo,
+    op_return::{
+        OpReturnOutput,
+        extract_op_return_outputs,
+        encode_op_return_outputs,
+        decode_op_return_outputs,
+    },
 };
 
 pub trait KVS: Send + Sync {
     fn get(&self, key: &str) -> Option<Binary>;
     fn set(&self, key: &str, value: &[u8]);
+    fn del(&self, key: &str);
+    // Get multiple values at once. The results are in the same order as the keys.
+    fn mget(&self, keys: &[String]) -> Vec<Option<Binary>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+    // Whether the KVS is reachable.
+    fn ping(&self) -> bool {
+        true
+    }
+    // Increment a counter stored as a decimal string and return the new value.
+    // The default implementation is not atomic.
+    fn incr(&self, key: &str, delta: i64) -> i64 {
+        let value = self.get(key)
+            .and_then(|value| String::from_utf8(value).ok())
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or(0) + delta;
+        self.set(key, value.to_string().as_bytes());
+        value
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -35,7 +74,7 @@ impl RedisClientPool {
     pub fn new(redis_url: &str) -> Self {
         let client = redis::Client::open(redis_url).unwrap();
         let pool_size = std::thread::available_parallelism().unwrap().get();
-        println!("Redis connection pool size: {}", pool_size);
+        tracing::info!(pool_size, "Redis connection pool created.");
         let pool = r2d2::Pool::builder().max_size(pool_size as u32).build(client).unwrap();
         Self {
             pool,
@@ -51,6 +90,26 @@ impl KVS for RedisClientPool {
     fn set(&self, key: &str, value: &[u8]) {
         let _: () = self.pool.get().unwrap().set(key, value).unwrap();
     }
+    fn del(&self, key: &str) {
+        let _: () = self.pool.get().unwrap().del(key).unwrap();
+    }
+    fn mget(&self, keys: &[String]) -> Vec<Option<Binary>> {
+        if keys.is_empty() {
+            return Vec::new();
+        }
+        let values: Vec<Option<Binary>> = redis::cmd("MGET").arg(keys).query(&mut *self.pool.get().unwrap()).unwrap();
+        values
+    }
+    fn incr(&self, key: &str, delta: i64) -> i64 {
+        let value: i64 = self.pool.get().unwrap().incr(key, delta).unwrap();
+        value
+    }
+    fn ping(&self) -> bool {
+        match self.pool.get() {
+            Ok(mut conn) => redis::cmd("PING").query::<String>(&mut *conn).is_ok(),
+            Err(_) => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -159,11 +218,49 @@ impl From<KVSBlock> for Binary {
     }
 }
 
+// Number of preceding blocks used to compute the median time past.
+const MEDIAN_TIME_SPAN: u32 = 11;
+// Number of heights read at once when searching OP_RETURN outputs.
+const OP_RETURN_SEARCH_BATCH_SIZE: u32 = 100;
+// Number of mempool entries read per MGET.
+const MEMPOOL_ENTRY_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockTime {
+    pub time: u32,
+    pub median_time: u32,
+    // Maximum timestamp of the blocks up to this height, which is monotonic unlike `time`.
+    pub max_time: u32,
+}
+
+impl From<BlockTime> for [u8; 12] {
+    fn from(block_time: BlockTime) -> Self {
+        let mut block_time_vec = [0u8; 12];
+        block_time_vec[0..4].copy_from_slice(&block_time.time.to_le_bytes());
+        block_time_vec[4..8].copy_from_slice(&block_time.median_time.to_le_bytes());
+        block_time_vec[8..12].copy_from_slice(&block_time.max_time.to_le_bytes());
+        block_time_vec
+    }
+}
+
+impl From<[u8; 12]> for BlockTime {
+    fn from(block_time_vec: [u8; 12]) -> Self {
+        Self {
+            time: u32::from_le_bytes(block_time_vec[0..4].try_into().unwrap()),
+            median_time: u32::from_le_bytes(block_time_vec[4..8].try_into().unwrap()),
+            max_time: u32::from_le_bytes(block_time_vec[8..12].try_into().unwrap()),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Client {
     prefix: String,
     chain: String,
     kvs: Arc<dyn KVS>,
+    network: Network,
+    op_return_index: bool,
+    mempool: bool,
 }
 
 impl Client {
@@ -172,8 +269,38 @@ impl Client {
             prefix: prefix.unwrap_or("bitcoin-rest-mirror".to_string()),
             chain,
             kvs: Arc::new(kvs),
+            network: Network::Bitcoin,
+            op_return_index: false,
+            mempool: false,
         }
     }
+    pub fn ping(&self) -> bool {
+        self.kvs.ping()
+    }
+    pub fn get_chain(&self) -> &str {
+        &self.chain
+    }
+    pub fn set_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+    pub fn get_network(&self) -> Network {
+        self.network
+    }
+    pub fn set_op_return_index(mut self, op_return_index: bool) -> Self {
+        self.op_return_index = op_return_index;
+        self
+    }
+    pub fn is_op_return_index_enabled(&self) -> bool {
+        self.op_return_index
+    }
+    pub fn set_mempool(mut self, mempool: bool) -> Self {
+        self.mempool = mempool;
+        self
+    }
+    pub fn is_mempool_enabled(&self) -> bool {
+        self.mempool
+    }
     fn get_key(&self, key_prefix: &str, key: &str) -> String {
         format!("{}:{}:{}:{}", self.prefix, self.chain, key_prefix, key)
     }
@@ -185,6 +312,14 @@ impl Client {
         let key = self.get_key(key_prefix, key);
         self.kvs.set(&key, &value);
     }
+    fn del(&self, key_prefix: &str, key: &str) {
+        let key = self.get_key(key_prefix, key);
+        self.kvs.del(&key);
+    }
+    fn mget(&self, key_prefix: &str, keys: &[String]) -> Vec<Option<Binary>> {
+        let keys = keys.iter().map(|key| self.get_key(key_prefix, key)).collect::<Vec<String>>();
+        self.kvs.mget(&keys)
+    }
     fn height_to_slice(height: u32) -> [u8; 4] {
         let mut height_vec = [0u8; 4];
         height_vec.copy_from_slice(&height.to_le_bytes());
@@ -205,6 +340,32 @@ impl Client {
             None => 0,
         }
     }
+    // API keys are shared among chains.
+    pub fn set_api_key(&self, api_key: &ApiKey) {
+        self.kvs.set(format!("{}:apiKey:{}", self.prefix, api_key.key).as_str(), &serde_json::to_vec(api_key).unwrap());
+    }
+    pub fn get_api_key(&self, key: &str) -> Option<ApiKey> {
+        let api_key = self.kvs.get(format!("{}:apiKey:{}", self.prefix, key).as_str())?;
+        serde_json::from_slice(&api_key).ok()
+    }
+    // Count a request made with an API key on this chain.
+    pub fn incr_api_key_usage(&self, key: &str) -> u64 {
+        self.kvs.incr(format!("{}:{}:apiKeyUsage:{}", self.prefix, self.chain, key).as_str(), 1) as u64
+    }
+    pub fn get_api_key_usage(&self, key: &str) -> u64 {
+        self.kvs.get(format!("{}:{}:apiKeyUsage:{}", self.prefix, self.chain, key).as_str())
+            .and_then(|usage| String::from_utf8(usage).ok())
+            .and_then(|usage| usage.parse::<u64>().ok())
+            .unwrap_or(0)
+    }
+    // Height of the best block known to the upstream node.
+    pub fn set_upstream_block_height(&self, height: u32) {
+        self.kvs.set(format!("{}:{}:upstreamBlockHeight", self.prefix, self.chain).as_str(), &Self::height_to_slice(height));
+    }
+    pub fn get_upstream_block_height(&self) -> Option<u32> {
+        let height_vec = self.kvs.get(format!("{}:{}:upstreamBlockHeight", self.prefix, self.chain).as_str())?;
+        Some(Self::slice_to_height(&height_vec.try_into().unwrap()))
+    }
     pub fn set_block_header(&self, block_hash: &[u8; 32], block_header: &[u8; 80]) {
         self.set("blockHeader", hex::encode(block_hash).as_str(), block_header);
     }
@@ -217,6 +378,12 @@ impl Client {
             None => None
         }
     }
+    pub fn get_block_headers(&self, block_hashes: &[[u8; 32]]) -> Vec<Option<[u8; 80]>> {
+        let keys = block_hashes.iter().map(hex::encode).collect::<Vec<String>>();
+        self.mget("blockHeader", &keys).into_iter().map(|block_header| {
+            block_header.and_then(|block_header| block_header.try_into().ok())
+        }).collect()
+    }
     pub fn set_block_hash_by_height(&self, height: u32, block_hash: &[u8; 32]) {
         self.set("blockHashByHeight", height.to_string().as_str(), block_hash);
     }
@@ -229,6 +396,194 @@ impl Client {
             None => None
         }
     }
+    pub fn get_block_hashes_by_height(&self, heights: std::ops::Range<u32>) -> Vec<Option<[u8; 32]>> {
+        let keys = heights.map(|height| height.to_string()).collect::<Vec<String>>();
+        self.mget("blockHashByHeight", &keys).into_iter().map(|block_hash| {
+            block_hash.and_then(|block_hash| block_hash.try_into().ok())
+        }).collect()
+    }
+    pub fn set_block_time_by_height(&self, height: u32, block_time: &BlockTime) {
+        let block_time_vec: [u8; 12] = (*block_time).into();
+        self.set("blockTimeByHeight", height.to_string().as_str(), &block_time_vec);
+    }
+    pub fn get_block_time_by_height(&self, height: u32) -> Option<BlockTime> {
+        let block_time_vec = self.get("blockTimeByHeight", height.to_string().as_str())?;
+        let block_time_vec: [u8; 12] = block_time_vec.try_into().unwrap();
+        Some(block_time_vec.into())
+    }
+    pub fn set_chain_work_by_height(&self, height: u32, chain_work: &Work) {
+        self.set("chainWorkByHeight", height.to_string().as_str(), &chain_work.to_be_bytes());
+    }
+    pub fn get_chain_work_by_height(&self, height: u32) -> Option<Work> {
+        let chain_work = self.get("chainWorkByHeight", height.to_string().as_str())?;
+        Some(Work::from_be_bytes(chain_work.try_into().unwrap()))
+    }
+    /*
+     * Register the per-height indexes which depend on the preceding blocks.
+     * Blocks must be indexed in height order after they are added. Heights whose preceding blocks
+     * are not indexed are skipped, and caught up by `index_missing_block_heights` at the next start.
+     * @return Whether the height was indexed.
+     */
+    pub fn index_block_height(&self, height: u32) -> bool {
+        let block_hash = self.get_block_hash_by_height(height).expect("Block hash not found");
+        let block_header = self.get_block_header(&block_hash).expect("Block header not found");
+        let block_header = Header::consensus_decode(&mut block_header.as_slice()).unwrap();
+        let prev_block_times = (height.saturating_sub(MEDIAN_TIME_SPAN - 1)..height)
+            .map(|prev_height| self.get_block_time_by_height(prev_height))
+            .collect::<Option<Vec<BlockTime>>>();
+        let prev_chain_work = match height {
+            0 => Some(None),
+            _ => self.get_chain_work_by_height(height - 1).map(Some),
+        };
+        let (prev_block_times, prev_chain_work) = match (prev_block_times, prev_chain_work) {
+            (Some(prev_block_times), Some(prev_chain_work)) => (prev_block_times, prev_chain_work),
+            _ => {
+                tracing::warn!(height, "Previous block is not indexed, skipping.");
+                return false;
+            },
+        };
+        // Compute the median time past.
+        let mut times = prev_block_times.iter().map(|block_time| block_time.time).collect::<Vec<u32>>();
+        times.push(block_header.time);
+        times.sort();
+        let median_time = times[times.len() / 2];
+        let max_time = match prev_block_times.last() {
+            Some(prev_block_time) => prev_block_time.max_time.max(block_header.time),
+            None => block_header.time,
+        };
+        self.set_block_time_by_height(height, &BlockTime {
+            time: block_header.time,
+            median_time,
+            max_time,
+        });
+        // Accumulate the chain work.
+        let chain_work = match prev_chain_work {
+            Some(prev_chain_work) => prev_chain_work + block_header.work(),
+            None => block_header.work(),
+        };
+        self.set_chain_work_by_height(height, &chain_work);
+        true
+    }
+    // Index the heights which were added before the per-height indexes were introduced.
+    pub fn index_missing_block_heights(&self) {
+        let next_block_height = self.get_next_block_height();
+        let is_indexed = |height: u32| {
+            self.get_block_time_by_height(height).is_some() && self.get_chain_work_by_height(height).is_some()
+        };
+        if next_block_height == 0 || is_indexed(next_block_height - 1) {
+            return;
+        }
+        for height in 0..next_block_height {
+            if !is_indexed(height) {
+                self.index_block_height(height);
+            }
+        }
+    }
+    /*
+     * Register the block hashes of the transactions of the blocks added before `blockHashByTransaction` was introduced.
+     * Blocks from the next block height at the first run onwards are added with the index, so only those below are indexed.
+     * The progress is saved so that the backfill resumes after a restart.
+     */
+    pub fn index_missing_transaction_blocks(&self) {
+        let key = format!("{}:{}:transactionBlocksBackfill", self.prefix, self.chain);
+        // Height of the next block to index and the height at which the backfill stops.
+        let (start_height, end_height) = match self.kvs.get(&key) {
+            Some(progress) => (
+                Self::slice_to_height(&progress[0..4].try_into().unwrap()),
+                Self::slice_to_height(&progress[4..8].try_into().unwrap()),
+            ),
+            None => {
+                let end_height = self.get_next_block_height();
+                self.kvs.set(&key, &[Self::height_to_slice(0), Self::height_to_slice(end_height)].concat());
+                (0, end_height)
+            },
+        };
+        if start_height >= end_height {
+            return;
+        }
+        tracing::info!(start_height, end_height, "Indexing the blocks of transactions...");
+        for height in start_height..end_height {
+            let block_hash = self.get_block_hash_by_height(height).expect("Block hash not found");
+            let tx_hashes = self.get_block_transaction_hashes(&block_hash).expect("Block transactions not found");
+            for tx_hash in tx_hashes.iter() {
+                self.set_block_hash_by_transaction(tx_hash, &block_hash);
+            }
+            if (height + 1) % 1000 == 0 || height + 1 == end_height {
+                self.kvs.set(&key, &[Self::height_to_slice(height + 1), Self::height_to_slice(end_height)].concat());
+                tracing::info!(height, end_height, "Indexed the blocks of transactions.");
+            }
+        }
+    }
+    // Compute the fee statistics of blocks which were added out of order.
+    pub fn index_missing_block_stats(&self) {
+        let key = format!("{}:{}:blockStatsBackfill", self.prefix, self.chain);
+        // Height of the next block to check.
+        let start_height = match self.kvs.get(&key) {
+            Some(progress) => Self::slice_to_height(&progress[0..4].try_into().unwrap()),
+            None => 0,
+        };
+        let end_height = self.get_next_block_height();
+        if start_height >= end_height {
+            return;
+        }
+        tracing::info!(start_height, end_height, "Computing missing block fee statistics...");
+        for height in start_height..end_height {
+            let block_hash = self.get_block_hash_by_height(height).expect("Block hash not found");
+            if self.get_block_stats(&block_hash).is_some_and(|block_stats| block_stats.totalfee.is_none()) {
+                let block = self.get_block(&block_hash).expect("Block not found");
+                let block = Block::consensus_decode(&mut block.as_slice()).unwrap();
+                let block_stats = BlockStats::compute(height, &block, |outpoint| self.get_output(outpoint));
+                self.set_block_stats(&block_hash, &block_stats);
+            }
+            if (height + 1) % 1000 == 0 || height + 1 == end_height {
+                self.kvs.set(&key, &Self::height_to_slice(height + 1));
+                tracing::info!(height, end_height, "Computed missing block fee statistics.");
+            }
+        }
+    }
+    // Get the position of an indexed block in the chain.
+    pub fn get_chain_position(&self, block_hash: &[u8; 32]) -> Option<ChainPosition> {
+        let height = self.get_block_height_by_hash(block_hash)?;
+        let next_block_height = self.get_next_block_height();
+        if height >= next_block_height {
+            return None;
+        }
+        let block_time = self.get_block_time_by_height(height)?;
+        let chain_work = self.get_chain_work_by_height(height)?;
+        let n_tx = match self.get_block_stats(block_hash) {
+            Some(block_stats) => block_stats.txs,
+            None => self.get_block_transaction_hashes(block_hash)?.len(),
+        };
+        let next_block_hash = match height + 1 < next_block_height {
+            true => self.get_block_hash_by_height(height + 1).map(BlockHash::from_byte_array),
+            false => None,
+        };
+        Some(ChainPosition {
+            height,
+            confirmations: next_block_height - height,
+            median_time: block_time.median_time,
+            chain_work,
+            n_tx,
+            next_block_hash,
+        })
+    }
+    // Find the first block whose timestamp is at or after the given time.
+    pub fn get_block_height_by_time(&self, time: u32) -> Option<u32> {
+        let mut low = 0;
+        let mut high = self.get_next_block_height();
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.get_block_time_by_height(mid)?.max_time >= time {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        if low >= self.get_next_block_height() {
+            return None;
+        }
+        Some(low)
+    }
     pub fn set_block_height_by_hash(&self, block_hash: &[u8; 32], height: u32) {
         self.set("blockHeightByHash", hex::encode(block_hash).as_str(), &Self::height_to_slice(height));
     }
@@ -267,9 +622,161 @@ impl Client {
     pub fn get_transaction(&self, tx_hash: &[u8; 32]) -> Option<Binary> {
         self.get("transaction", hex::encode(tx_hash).as_str())
     }
+    pub fn get_transactions(&self, tx_hashes: &[[u8; 32]]) -> Vec<Option<Binary>> {
+        let keys = tx_hashes.iter().map(hex::encode).collect::<Vec<String>>();
+        self.mget("transaction", &keys)
+    }
+    pub fn set_block_hash_by_transaction(&self, tx_hash: &[u8; 32], block_hash: &[u8; 32]) {
+        self.set("blockHashByTransaction", hex::encode(tx_hash).as_str(), block_hash);
+    }
+    // Blocks registered before this index was introduced have no entries until `index_missing_transaction_blocks` is done.
+    pub fn get_block_hash_by_transaction(&self, tx_hash: &[u8; 32]) -> Option<[u8; 32]> {
+        let block_hash = self.get("blockHashByTransaction", hex::encode(tx_hash).as_str())?;
+        block_hash.try_into().ok()
+    }
+    pub fn set_txid_by_wtxid(&self, wtx_hash: &[u8; 32], tx_hash: &[u8; 32]) {
+        self.set("txidByWtxid", hex::encode(wtx_hash).as_str(), tx_hash);
+    }
+    // Only transactions with witness data are registered, as their wtxid equals to the txid otherwise.
+    pub fn get_txid_by_wtxid(&self, wtx_hash: &[u8; 32]) -> Option<[u8; 32]> {
+        let tx_hash = self.get("txidByWtxid", hex::encode(wtx_hash).as_str())?;
+        Some(tx_hash.try_into().unwrap())
+    }
+    // Get a transaction by either its txid or its wtxid.
+    pub fn get_transaction_by_txid_or_wtxid(&self, hash: &[u8; 32]) -> Option<Binary> {
+        if let Some(tx) = self.get_transaction(hash) {
+            return Some(tx);
+        }
+        let tx_hash = self.get_txid_by_wtxid(hash)?;
+        self.get_transaction(&tx_hash)
+    }
+    pub fn set_miner_info(&self, block_hash: &[u8; 32], miner_info: &MinerInfo) {
+        self.set("minerInfo", hex::encode(block_hash).as_str(), &serde_json::to_vec(miner_info).unwrap());
+    }
+    pub fn get_miner_info(&self, block_hash: &[u8; 32]) -> Option<MinerInfo> {
+        let miner_info = self.get("minerInfo", hex::encode(block_hash).as_str())?;
+        Some(serde_json::from_slice(&miner_info).unwrap())
+    }
+    pub fn set_op_return_outputs(&self, height: u32, outputs: &[OpReturnOutput]) {
+        self.set("opReturnOutputsByHeight", height.to_string().as_str(), &encode_op_return_outputs(outputs));
+    }
+    pub fn get_op_return_outputs(&self, height: u32) -> Vec<OpReturnOutput> {
+        match self.get("opReturnOutputsByHeight", height.to_string().as_str()) {
+            Some(outputs_vec) => decode_op_return_outputs(height, &outputs_vec),
+            None => Vec::new(),
+        }
+    }
+    /*
+     * Search OP_RETURN outputs whose payload starts with `prefix` in heights [from, to).
+     * Search starts from the `skip`-th output of the `from` block.
+     * Outputs are indexed by height only, so every height in the range is read (in batches of `OP_RETURN_SEARCH_BATCH_SIZE`).
+     * @return (outputs, next cursor as (height, skip))
+     */
+    pub fn search_op_return_outputs(&self, prefix: &[u8], from: u32, to: u32, skip: usize, limit: usize) -> (Vec<OpReturnOutput>, Option<(u32, usize)>) {
+        let to = to.min(self.get_next_block_height());
+        let mut outputs = Vec::new();
+        for batch_from in (from..to).step_by(OP_RETURN_SEARCH_BATCH_SIZE as usize) {
+            let heights = (batch_from..to.min(batch_from + OP_RETURN_SEARCH_BATCH_SIZE)).collect::<Vec<u32>>();
+            let keys = heights.iter().map(|height| height.to_string()).collect::<Vec<String>>();
+            let batch_outputs = self.mget("opReturnOutputsByHeight", &keys);
+            for (height, outputs_vec) in heights.into_iter().zip(batch_outputs) {
+                let block_outputs = match outputs_vec {
+                    Some(outputs_vec) => decode_op_return_outputs(height, &outputs_vec),
+                    None => continue,
+                };
+                let skip = if height == from { skip } else { 0 };
+                for (index, output) in block_outputs.into_iter().enumerate().skip(skip) {
+                    if !output.payload.starts_with(prefix) {
+                        continue;
+                    }
+                    if outputs.len() >= limit {
+                        return (outputs, Some((height, index)));
+                    }
+                    outputs.push(output);
+                }
+            }
+        }
+        let next = if to < self.get_next_block_height() { Some((to, 0)) } else { None };
+        (outputs, next)
+    }
+    pub fn set_mempool_txids(&self, tx_hashes: &[[u8; 32]]) {
+        self.set("mempool", "txids", &tx_hashes.concat());
+    }
+    pub fn get_mempool_txids(&self) -> Vec<[u8; 32]> {
+        match self.get("mempool", "txids") {
+            Some(tx_hashes) => tx_hashes.chunks_exact(32).map(|tx_hash| tx_hash.try_into().unwrap()).collect(),
+            None => Vec::new(),
+        }
+    }
+    // Mempool information as returned by the upstream node.
+    pub fn set_mempool_info(&self, info: &serde_json::Value) {
+        self.set("mempool", "info", &serde_json::to_vec(info).unwrap());
+    }
+    pub fn get_mempool_info(&self) -> Option<serde_json::Value> {
+        let info = self.get("mempool", "info")?;
+        Some(serde_json::from_slice(&info).unwrap())
+    }
+    pub fn set_mempool_transaction(&self, tx_hash: &[u8; 32], tx: &[u8], entry: &serde_json::Value) {
+        self.set("mempool:transaction", hex::encode(tx_hash).as_str(), tx);
+        self.set("mempool:entry", hex::encode(tx_hash).as_str(), &serde_json::to_vec(entry).unwrap());
+    }
+    pub fn get_mempool_transaction(&self, tx_hash: &[u8; 32]) -> Option<Binary> {
+        self.get("mempool:transaction", hex::encode(tx_hash).as_str())
+    }
+    pub fn get_mempool_entry(&self, tx_hash: &[u8; 32]) -> Option<serde_json::Value> {
+        let entry = self.get("mempool:entry", hex::encode(tx_hash).as_str())?;
+        Some(serde_json::from_slice(&entry).unwrap())
+    }
+    // Read the entries of many mempool transactions (in batches of `MEMPOOL_ENTRY_BATCH_SIZE`).
+    pub fn get_mempool_entries(&self, tx_hashes: &[[u8; 32]]) -> Vec<Option<serde_json::Value>> {
+        let mut entries = Vec::with_capacity(tx_hashes.len());
+        for batch in tx_hashes.chunks(MEMPOOL_ENTRY_BATCH_SIZE) {
+            let keys = batch.iter().map(hex::encode).collect::<Vec<String>>();
+            let batch_entries = self.mget("mempool:entry", &keys);
+            entries.extend(batch_entries.into_iter().map(|entry| entry.map(|entry| serde_json::from_slice(&entry).unwrap())));
+        }
+        entries
+    }
+    pub fn remove_mempool_transaction(&self, tx_hash: &[u8; 32]) {
+        self.del("mempool:transaction", hex::encode(tx_hash).as_str());
+        self.del("mempool:entry", hex::encode(tx_hash).as_str());
+    }
+    // Resolve an output from the registered transactions.
+    pub fn get_output(&self, outpoint: &OutPoint) -> Option<TxOut> {
+        let tx = self.get_transaction(outpoint.txid.as_ref())?;
+        let tx = Transaction::consensus_decode(&mut tx.as_slice()).unwrap();
+        tx.output.get(outpoint.vout as usize).cloned()
+    }
+    pub fn set_block_stats(&self, block_hash: &[u8; 32], block_stats: &BlockStats) {
+        self.set("blockStats", hex::encode(block_hash).as_str(), &serde_json::to_vec(block_stats).unwrap());
+    }
+    pub fn get_block_stats(&self, block_hash: &[u8; 32]) -> Option<BlockStats> {
+        let block_stats = self.get("blockStats", hex::encode(block_hash).as_str())?;
+        Some(serde_json::from_slice(&block_stats).unwrap())
+    }
     pub fn add_block(&self, height: u32, block_bytes: Binary, set_next_block_height: Option<bool>) {
         let block = Block::consensus_decode(&mut block_bytes.as_slice()).unwrap();
         let block_hash: [u8; 32] = *block.block_hash().as_ref();
+        let set_next_block_height = set_next_block_height.unwrap_or(true);
+        /*
+         * Compute block statistics.
+         * The fee statistics cost one KVS read and one transaction decode per input. The spent outputs are only
+         * guaranteed to be registered when blocks are added in order, otherwise they are computed by `index_block_height`.
+         */
+        let block_stats = match set_next_block_height {
+            true => BlockStats::compute(height, &block, |outpoint| self.get_output(outpoint)),
+            false => BlockStats::compute(height, &block, |_| None),
+        };
+        self.set_block_stats(&block_hash, &block_stats);
+        // Register miner information.
+        self.set_miner_info(&block_hash, &MinerInfo::new(height, &block));
+        // Register OP_RETURN outputs.
+        if self.op_return_index {
+            let outputs = extract_op_return_outputs(height, &block);
+            if !outputs.is_empty() {
+                self.set_op_return_outputs(height, &outputs);
+            }
+        }
         // Register transactions and hashes.
         let mut tx_hashes = Vec::new();
         for tx in block.txdata {
@@ -278,6 +785,16 @@ impl Client {
             let mut tx_vec = Vec::new();
             tx.consensus_encode(&mut tx_vec).unwrap();
             self.set_transaction(&tx_hash, &tx_vec);
+            self.set_block_hash_by_transaction(&tx_hash, &block_hash);
+            // Evict the confirmed transaction from the mempool. The txid list is refreshed by the mempool poll.
+            if self.mempool {
+                self.remove_mempool_transaction(&tx_hash);
+            }
+            // Register witness transaction hash (the coinbase wtxid is defined as zero by BIP141).
+            let wtx_hash: [u8; 32] = *tx.compute_wtxid().as_ref();
+            if !tx.is_coinbase() && wtx_hash != tx_hash {
+                self.set_txid_by_wtxid(&wtx_hash, &tx_hash);
+            }
         }
         // Register block transaction hashes.
         self.set_block_transaction_hashes(&block_hash, &tx_hashes);
@@ -290,8 +807,8 @@ impl Client {
         // Set block hash by height.
         self.set_block_hash_by_height(height, &block_hash);
         // Set next block height.
-        let set_next_block_height = set_next_block_height.unwrap_or(true);
         if set_next_block_height {
+            self.index_block_height(height);
             self.set_next_block_height(height + 1);
         }
     }
@@ -352,6 +869,9 @@ pub mod tests {
         fn set(&self, key: &str, value: &[u8]) {
             self.db.lock().unwrap().insert(key.to_string(), value.to_vec());
         }
+        fn del(&self, key: &str) {
+            self.db.lock().unwrap().remove(key);
+        }
     }
     
     pub fn create_client() -> Client {
@@ -360,6 +880,19 @@ pub mod tests {
         client
     }
     
+    // Client with the first `count` fixture blocks added in order.
+    pub fn create_synced_client(count: usize) -> Client {
+        let client = create_client();
+        for (height, block) in load_blocks().iter().enumerate().take(count) {
+            client.add_block(height as u32, block.clone(), None);
+        }
+        client
+    }
+    
+    pub fn load_decoded_blocks(count: usize) -> Vec<Block> {
+        load_blocks().iter().take(count).map(|block| Block::consensus_decode(&mut block.as_slice()).unwrap()).collect()
+    }
+    
     pub fn load_blocks() -> Vec<Binary> {
         let mut blocks: Vec<Binary> = Vec::new();
         for height in 0..1000 {
@@ -466,5 +999,137 @@ pub mod tests {
         }
     }
     
+    mod txid_by_wtxid {
+        use super::*;
+        #[test]
+        fn get_none() {
+            let client = create_client();
+            assert_eq!(client.get_txid_by_wtxid(&[0u8; 32]), None);
+        }
+        #[test]
+        fn add_block() {
+            let client = create_client();
+            let mut f = File::open("./fixture/blocks/block_800000.bin").unwrap();
+            let mut block_bytes = Vec::new();
+            f.read_to_end(&mut block_bytes).unwrap();
+            let block = Block::consensus_decode(&mut block_bytes.as_slice()).unwrap();
+            client.add_block(800_000, block_bytes, Some(false));
+            for tx in block.txdata.iter().skip(1) {
+                let tx_hash: [u8; 32] = *tx.compute_txid().as_ref();
+                let wtx_hash: [u8; 32] = *tx.compute_wtxid().as_ref();
+                let mut tx_vec = Vec::new();
+                tx.consensus_encode(&mut tx_vec).unwrap();
+                assert_eq!(client.get_transaction_by_txid_or_wtxid(&wtx_hash), Some(tx_vec));
+                if wtx_hash != tx_hash {
+                    assert_eq!(client.get_txid_by_wtxid(&wtx_hash), Some(tx_hash));
+                }
+            }
+        }
+    }
+    
+    mod block_hash_by_transaction {
+        use super::*;
+        #[test]
+        fn backfill() {
+            let client = create_synced_client(10);
+            let blocks = load_blocks();
+            // Remove the entries as if the blocks were added by an older version.
+            let block_hash = client.get_block_hash_by_height(5).unwrap();
+            let tx_hash = client.get_block_transaction_hashes(&block_hash).unwrap()[0];
+            client.del("blockHashByTransaction", hex::encode(tx_hash).as_str());
+            assert_eq!(client.get_block_hash_by_transaction(&tx_hash), None);
+            client.index_missing_transaction_blocks();
+            assert_eq!(client.get_block_hash_by_transaction(&tx_hash), Some(block_hash));
+            // Blocks added afterwards are not indexed again.
+            client.add_block(10, blocks[10].clone(), None);
+            client.del("blockHashByTransaction", hex::encode(tx_hash).as_str());
+            client.index_missing_transaction_blocks();
+            assert_eq!(client.get_block_hash_by_transaction(&tx_hash), None);
+        }
+    }
+    
+    mod block_time_by_height {
+        use super::*;
+        #[test]
+        fn get_none() {
+            let client = create_client();
+            assert_eq!(client.get_block_time_by_height(0), None);
+            assert_eq!(client.get_block_height_by_time(0), None);
+        }
+        #[test]
+        fn index() {
+            let client = create_client();
+            let blocks = load_blocks();
+            let times = blocks.iter().take(20).map(|block| {
+                Block::consensus_decode(&mut block.as_slice()).unwrap().header.time
+            }).collect::<Vec<u32>>();
+            for (height, block) in blocks.iter().take(20).enumerate() {
+                client.add_block(height as u32, block.clone(), None);
+            }
+            let block_time = client.get_block_time_by_height(15).unwrap();
+            let mut median_times = times[5..=15].to_vec();
+            median_times.sort();
+            assert_eq!(block_time.time, times[15]);
+            assert_eq!(block_time.median_time, median_times[5]);
+            assert_eq!(block_time.max_time, *times[0..=15].iter().max().unwrap());
+            assert_eq!(client.get_block_height_by_time(times[15]), Some(15));
+            assert_eq!(client.get_block_height_by_time(times[19] + 1), None);
+        }
+        #[test]
+        fn skip_unindexed() {
+            let client = create_client();
+            let blocks = load_blocks();
+            for (height, block) in blocks.iter().enumerate().take(3) {
+                client.add_block(height as u32, block.clone(), Some(false));
+            }
+            assert!(!client.index_block_height(2));
+            assert_eq!(client.get_block_time_by_height(2), None);
+            client.set_next_block_height(3);
+            client.index_missing_block_heights();
+            assert!(client.get_block_time_by_height(2).is_some());
+        }
+    }
+    
+    mod block_stats {
+        use super::*;
+        #[test]
+        fn out_of_order() {
+            let client = create_client();
+            let blocks = load_blocks();
+            for (height, block) in blocks.iter().enumerate().take(171).rev() {
+                client.add_block(height as u32, block.clone(), Some(false));
+            }
+            let block_hash = client.get_block_hash_by_height(170).unwrap();
+            assert_eq!(client.get_block_stats(&block_hash).unwrap().totalfee, None);
+            for height in 0..=170 {
+                assert!(client.index_block_height(height));
+                client.set_next_block_height(height + 1);
+            }
+            assert_eq!(client.get_block_stats(&block_hash).unwrap().totalfee, None);
+            client.index_missing_block_stats();
+            let block_stats = client.get_block_stats(&block_hash).unwrap();
+            assert_eq!(block_stats.totalfee, Some(0));
+            assert_eq!(block_stats.feerate_percentiles, Some([0; 5]));
+        }
+    }
+    
+    mod mempool {
+        use super::*;
+        #[test]
+        fn evict_confirmed() {
+            let client = create_client().set_mempool(true);
+            let mut f = File::open("./fixture/blocks/block_800000.bin").unwrap();
+            let mut block_bytes = Vec::new();
+            f.read_to_end(&mut block_bytes).unwrap();
+            let block = Block::consensus_decode(&mut block_bytes.as_slice()).unwrap();
+            let tx_hash: [u8; 32] = *block.txdata[1].compute_txid().as_ref();
+            client.set_mempool_transaction(&tx_hash, &[0u8; 4], &serde_json::json!({}));
+            assert!(client.get_mempool_transaction(&tx_hash).is_some());
+            client.add_block(800_000, block_bytes, Some(false));
+            assert_eq!(client.get_mempool_transaction(&tx_hash), None);
+            assert_eq!(client.get_mempool_entry(&tx_hash), None);
+        }
+    }
+    
 }
 
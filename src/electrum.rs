@@ -0,0 +1,573 @@
+/*
+ * Electrum protocol (1.4) server over TCP, backed by the mirrored data.
+ * Requests and responses are newline-delimited JSON-RPC 2.0 messages.
+ * The mirror has no address index, so the `blockchain.scripthash.*` methods are rejected.
+ *
+ * API keys are not supported, so only the connection, batch and per-IP request limits below apply.
+ * Do not expose the port publicly without a proxy enforcing the rest.
+ */
+
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::{
+    json,
+    Value,
+};
+use tokio::io::{
+    AsyncBufReadExt,
+    AsyncReadExt,
+    AsyncWriteExt,
+    BufReader,
+};
+use tokio::net::{
+    TcpListener,
+    TcpStream,
+};
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+use yaml_rust2::Yaml;
+
+use crate::{
+    client::Client,
+    merkle::{
+        hash_to_string,
+        merkle_branch,
+    },
+    rate_limiter::{
+        Budget,
+        RateLimitConfig,
+        RateLimiter,
+    },
+    rpc,
+};
+
+pub const PROTOCOL_VERSION: &str = "1.4";
+const SERVER_VERSION: &str = concat!("bitcoin-rest-mirror ", env!("CARGO_PKG_VERSION"));
+// Maximum number of headers returned by `blockchain.block.headers`.
+const MAX_HEADERS_COUNT: u32 = 2016;
+// Connections sending longer lines are closed.
+const MAX_LINE_LENGTH: u64 = 1024 * 1024;
+// Interval at which the tip is checked for `blockchain.headers.subscribe`.
+const TIP_POLL_INTERVAL: Duration = Duration::from_millis(1000);
+
+// Error codes of ElectrumX.
+pub const BAD_REQUEST: i32 = 1;
+pub const DAEMON_ERROR: i32 = 2;
+pub const EXCESSIVE_RESOURCE_USAGE: i32 = -101;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_REQUEST: i32 = -32600;
+pub const PARSE_ERROR: i32 = -32700;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElectrumError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl ElectrumError {
+    fn new(code: i32, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<rpc::RpcError> for ElectrumError {
+    fn from(error: rpc::RpcError) -> Self {
+        Self::new(DAEMON_ERROR, &error.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ElectrumConfig {
+    // Connections beyond this are closed right away.
+    pub max_connections: usize,
+    // Batches with more requests are rejected.
+    pub max_batch_size: usize,
+    // Request budget of each IP address. Every request of a batch consumes a token.
+    pub budget: Budget,
+}
+
+impl Default for ElectrumConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 100,
+            max_batch_size: 100,
+            budget: Budget { rate: 20.0, burst: 100.0 },
+        }
+    }
+}
+
+impl ElectrumConfig {
+    // Load from the `electrum` section of a chain config.
+    pub fn from_yaml(yaml: &Yaml) -> Self {
+        let default = Self::default();
+        Self {
+            max_connections: yaml["maxConnections"].as_i64().map(|v| v as usize).unwrap_or(default.max_connections),
+            max_batch_size: yaml["maxBatchSize"].as_i64().map(|v| v as usize).unwrap_or(default.max_batch_size),
+            budget: Budget::from_yaml(&yaml["rateLimit"], default.budget),
+        }
+    }
+}
+
+// State of a connection.
+#[derive(Debug, Default)]
+pub struct Session {
+    // Height of the last tip sent to a client subscribed to headers.
+    subscribed_height: Option<u32>,
+    // Rate limiter shared by the connections and the IP address of the peer.
+    rate_limiter: Option<(Arc<RateLimiter>, String)>,
+}
+
+fn get_u32_param(params: &Value, index: usize, name: &str, default: Option<u32>) -> Result<u32, ElectrumError> {
+    match params.get(index) {
+        None | Some(Value::Null) => default.ok_or_else(|| ElectrumError::new(BAD_REQUEST, &format!("missing {}", name))),
+        Some(value) => value.as_u64()
+            .and_then(|value| u32::try_from(value).ok())
+            .ok_or_else(|| ElectrumError::new(BAD_REQUEST, &format!("{} should be a non-negative integer: {}", name, value))),
+    }
+}
+
+fn get_hash_param(params: &Value, index: usize) -> Result<String, ElectrumError> {
+    let hash = params.get(index).and_then(|hash| hash.as_str()).unwrap_or("");
+    if hash.len() != 64 || hex::decode(hash).is_err() {
+        return Err(ElectrumError::new(BAD_REQUEST, &format!("{} should be a transaction hash", params.get(index).unwrap_or(&Value::Null))));
+    }
+    Ok(hash.to_string())
+}
+
+fn get_header_by_height(client: &Client, height: u32) -> Result<[u8; 80], ElectrumError> {
+    if height >= client.get_next_block_height() {
+        return Err(ElectrumError::new(BAD_REQUEST, &format!("height {} out of range", height)));
+    }
+    client.get_block_hash_by_height(height)
+        .and_then(|block_hash| client.get_block_header(&block_hash))
+        .ok_or_else(|| ElectrumError::new(DAEMON_ERROR, "Block not found"))
+}
+
+// Checkpoint proofs would require hashing every header up to the checkpoint.
+fn check_cp_height(params: &Value, index: usize) -> Result<(), ElectrumError> {
+    match get_u32_param(params, index, "cp_height", Some(0))? {
+        0 => Ok(()),
+        _ => Err(ElectrumError::new(BAD_REQUEST, "cp_height is not supported")),
+    }
+}
+
+fn get_tip_header(client: &Client) -> Result<(u32, [u8; 80]), ElectrumError> {
+    let next_block_height = client.get_next_block_height();
+    if next_block_height == 0 {
+        return Err(ElectrumError::new(DAEMON_ERROR, "No blocks synced yet"));
+    }
+    let height = next_block_height - 1;
+    Ok((height, get_header_by_height(client, height)?))
+}
+
+fn block_headers(client: &Client, params: &Value) -> Result<Value, ElectrumError> {
+    let start_height = get_u32_param(params, 0, "start_height", None)?;
+    let count = get_u32_param(params, 1, "count", None)?.min(MAX_HEADERS_COUNT);
+    check_cp_height(params, 2)?;
+    let next_block_height = client.get_next_block_height();
+    let end_height = start_height.saturating_add(count).min(next_block_height).max(start_height);
+    let block_hashes = client.get_block_hashes_by_height(start_height..end_height)
+        .into_iter()
+        .map(|block_hash| block_hash.ok_or_else(|| ElectrumError::new(DAEMON_ERROR, "Block not found")))
+        .collect::<Result<Vec<[u8; 32]>, ElectrumError>>()?;
+    let headers = client.get_block_headers(&block_hashes)
+        .into_iter()
+        .map(|header| header.ok_or_else(|| ElectrumError::new(DAEMON_ERROR, "Block not found")))
+        .collect::<Result<Vec<[u8; 80]>, ElectrumError>>()?;
+    Ok(json!({
+        "count": headers.len(),
+        "hex": hex::encode(headers.concat()),
+        "max": MAX_HEADERS_COUNT,
+    }))
+}
+
+fn transaction_get_merkle(client: &Client, params: &Value) -> Result<Value, ElectrumError> {
+    let tx_hash = get_hash_param(params, 0)?;
+    let height = get_u32_param(params, 1, "height", None)?;
+    let not_in_block = || ElectrumError::new(BAD_REQUEST, &format!("tx {} not in block at height {}", tx_hash, height));
+    let tx_hashes = client.get_block_hash_by_height(height)
+        .and_then(|block_hash| client.get_block_transaction_hashes(&block_hash))
+        .ok_or_else(not_in_block)?;
+    let mut hash = hex::decode(&tx_hash).unwrap();
+    hash.reverse();
+    let pos = tx_hashes.iter().position(|tx_hash| tx_hash[..] == hash[..]).ok_or_else(not_in_block)?;
+    let merkle = merkle_branch(&tx_hashes, pos).ok_or_else(not_in_block)?.iter().map(hash_to_string).collect::<Vec<String>>();
+    Ok(json!({
+        "block_height": height,
+        "merkle": merkle,
+        "pos": pos,
+    }))
+}
+
+fn transaction_id_from_pos(client: &Client, params: &Value) -> Result<Value, ElectrumError> {
+    let height = get_u32_param(params, 0, "height", None)?;
+    let tx_pos = get_u32_param(params, 1, "tx_pos", None)? as usize;
+    let merkle = params.get(2).and_then(|merkle| merkle.as_bool()).unwrap_or(false);
+    let tx_hashes = client.get_block_hash_by_height(height)
+        .and_then(|block_hash| client.get_block_transaction_hashes(&block_hash))
+        .ok_or_else(|| ElectrumError::new(BAD_REQUEST, &format!("block at height {} not found", height)))?;
+    let no_tx = || ElectrumError::new(BAD_REQUEST, &format!("no tx at position {} in block at height {}", tx_pos, height));
+    let tx_hash = tx_hashes.get(tx_pos).ok_or_else(no_tx)?;
+    if !merkle {
+        return Ok(json!(hash_to_string(tx_hash)));
+    }
+    let merkle = merkle_branch(&tx_hashes, tx_pos).ok_or_else(no_tx)?.iter().map(hash_to_string).collect::<Vec<String>>();
+    Ok(json!({
+        "tx_hash": hash_to_string(tx_hash),
+        "merkle": merkle,
+    }))
+}
+
+pub fn call(client: &Client, session: &mut Session, method: &str, params: &Value) -> Result<Value, ElectrumError> {
+    match method {
+        "server.version" => Ok(json!([SERVER_VERSION, PROTOCOL_VERSION])),
+        "server.banner" => Ok(json!(format!("Welcome to {}", SERVER_VERSION))),
+        "server.donation_address" => Ok(json!("")),
+        "server.peers.subscribe" => Ok(json!([])),
+        "server.ping" => Ok(Value::Null),
+        "server.features" => {
+            let genesis_hash = client.get_block_hash_by_height(0).map(|block_hash| hash_to_string(&block_hash));
+            Ok(json!({
+                "genesis_hash": genesis_hash,
+                "hosts": {},
+                "protocol_max": PROTOCOL_VERSION,
+                "protocol_min": PROTOCOL_VERSION,
+                "pruning": null,
+                "server_version": SERVER_VERSION,
+                "hash_function": "sha256",
+            }))
+        },
+        "blockchain.block.header" => {
+            let height = get_u32_param(params, 0, "height", None)?;
+            check_cp_height(params, 1)?;
+            Ok(json!(hex::encode(get_header_by_height(client, height)?)))
+        },
+        "blockchain.block.headers" => block_headers(client, params),
+        "blockchain.headers.subscribe" => {
+            let (height, header) = get_tip_header(client)?;
+            session.subscribed_height = Some(height);
+            Ok(json!({
+                "height": height,
+                "hex": hex::encode(header),
+            }))
+        },
+        "blockchain.transaction.get" => {
+            let tx_hash = get_hash_param(params, 0)?;
+            let verbose = params.get(1).and_then(|verbose| verbose.as_bool()).unwrap_or(false);
+            Ok(rpc::call(client, "getrawtransaction", &json!([tx_hash, verbose]))?)
+        },
+        "blockchain.transaction.get_merkle" => transaction_get_merkle(client, params),
+        "blockchain.transaction.id_from_pos" => transaction_id_from_pos(client, params),
+        _ if method.starts_with("blockchain.scripthash.") => {
+            Err(ElectrumError::new(BAD_REQUEST, "scripthash methods are not supported: the server has no address index"))
+        },
+        _ => Err(ElectrumError::new(METHOD_NOT_FOUND, &format!("unknown method \"{}\"", method))),
+    }
+}
+
+fn make_reply(id: &Value, result: Result<Value, ElectrumError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(error) => json!({ "jsonrpc": "2.0", "error": { "code": error.code, "message": error.message }, "id": id }),
+    }
+}
+
+fn handle_request(client: &Client, session: &mut Session, request: &Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    if let Some((rate_limiter, ip)) = &session.rate_limiter {
+        if rate_limiter.check(ip, false, false).is_err() {
+            return make_reply(&id, Err(ElectrumError::new(EXCESSIVE_RESOURCE_USAGE, "too many requests")));
+        }
+    }
+    let method = match request.get("method").and_then(|method| method.as_str()) {
+        Some(method) => method,
+        None => return make_reply(&id, Err(ElectrumError::new(INVALID_REQUEST, "invalid request"))),
+    };
+    let params = request.get("params").cloned().unwrap_or(json!([]));
+    make_reply(&id, call(client, session, method, &params))
+}
+
+// Handle a line, which is either a request object or a batch.
+pub fn handle_line(client: &Client, config: &ElectrumConfig, session: &mut Session, line: &str) -> Value {
+    match serde_json::from_str::<Value>(line) {
+        Ok(Value::Array(requests)) if requests.len() > config.max_batch_size => {
+            let message = format!("batch too large: {} > {}", requests.len(), config.max_batch_size);
+            make_reply(&Value::Null, Err(ElectrumError::new(INVALID_REQUEST, &message)))
+        },
+        Ok(Value::Array(requests)) => {
+            Value::Array(requests.iter().map(|request| handle_request(client, session, request)).collect())
+        },
+        Ok(request) => handle_request(client, session, &request),
+        Err(_) => make_reply(&Value::Null, Err(ElectrumError::new(PARSE_ERROR, "invalid JSON"))),
+    }
+}
+
+// Notification of a new tip if the session is subscribed to headers.
+fn poll_tip(client: &Client, session: &mut Session) -> Option<Value> {
+    let subscribed_height = session.subscribed_height?;
+    let (height, header) = get_tip_header(client).ok()?;
+    if height == subscribed_height {
+        return None;
+    }
+    session.subscribed_height = Some(height);
+    Some(json!({
+        "jsonrpc": "2.0",
+        "method": "blockchain.headers.subscribe",
+        "params": [{
+            "height": height,
+            "hex": hex::encode(header),
+        }],
+    }))
+}
+
+// Run a call on the blocking thread pool, as the client reads from the KVS synchronously.
+async fn run_blocking<T, F>(session: &mut Session, f: F) -> std::io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Session) -> T + Send + 'static,
+{
+    let mut owned_session = std::mem::take(session);
+    let (result, owned_session) = tokio::task::spawn_blocking(move || {
+        let result = f(&mut owned_session);
+        (result, owned_session)
+    }).await.map_err(std::io::Error::other)?;
+    *session = owned_session;
+    Ok(result)
+}
+
+async fn handle_connection(client: Client, config: Arc<ElectrumConfig>, mut session: Session, stream: TcpStream) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut interval = tokio::time::interval(TIP_POLL_INTERVAL);
+    // `read_until` is cancel safe: partially read lines are kept in the buffer across the ticks of the interval.
+    let mut line = Vec::new();
+    loop {
+        let mut limited_reader = (&mut reader).take(MAX_LINE_LENGTH - line.len() as u64);
+        let message = tokio::select! {
+            read = limited_reader.read_until(b'\n', &mut line) => {
+                if read? == 0 || line.last() != Some(&b'\n') {
+                    if line.len() as u64 >= MAX_LINE_LENGTH {
+                        tracing::warn!("Line too long, closing the connection.");
+                    }
+                    return Ok(());
+                }
+                let message = match std::str::from_utf8(&line).map(str::trim) {
+                    Ok("") => None,
+                    Ok(request) => {
+                        let (client, config, request) = (client.clone(), config.clone(), request.to_string());
+                        Some(run_blocking(&mut session, move |session| handle_line(&client, &config, session, &request)).await?)
+                    },
+                    Err(_) => Some(make_reply(&Value::Null, Err(ElectrumError::new(PARSE_ERROR, "invalid UTF-8")))),
+                };
+                line.clear();
+                match message {
+                    Some(message) => message,
+                    None => continue,
+                }
+            },
+            _ = interval.tick() => {
+                if session.subscribed_height.is_none() {
+                    continue;
+                }
+                let client = client.clone();
+                match run_blocking(&mut session, move |session| poll_tip(&client, session)).await? {
+                    Some(notification) => notification,
+                    None => continue,
+                }
+            },
+        };
+        let mut message = serde_json::to_vec(&message).unwrap();
+        message.push(b'\n');
+        writer.write_all(&message).await?;
+    }
+}
+
+pub async fn serve(client: Client, config: ElectrumConfig, listener: TcpListener) {
+    let config = Arc::new(config);
+    let connections = Arc::new(Semaphore::new(config.max_connections));
+    let rate_limiter = Arc::new(RateLimiter::new(RateLimitConfig {
+        enabled: true,
+        ip: config.budget.clone(),
+        ..RateLimitConfig::default()
+    }));
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to accept an Electrum connection.");
+                continue;
+            },
+        };
+        let connection = match connections.clone().try_acquire_owned() {
+            Ok(connection) => connection,
+            Err(_) => {
+                tracing::warn!(peer = %addr, "Too many Electrum connections, closing the connection.");
+                continue;
+            },
+        };
+        let client = client.clone();
+        let config = config.clone();
+        let session = Session {
+            rate_limiter: Some((rate_limiter.clone(), addr.ip().to_string())),
+            ..Session::default()
+        };
+        let span = tracing::info_span!("electrum", peer = %addr);
+        tokio::spawn(async move {
+            let _connection = connection;
+            tracing::debug!("Connection opened.");
+            if let Err(e) = handle_connection(client, config, session, stream).await {
+                tracing::debug!(error = %e, "Connection failed.");
+            }
+            tracing::debug!("Connection closed.");
+        }.instrument(span));
+    }
+}
+
+pub async fn start_electrum_server(client: Client, port: u16, host: &str, config: ElectrumConfig) {
+    let addr = format!("{}:{}", host, port);
+    let listener = TcpListener::bind(addr.clone()).await.unwrap();
+    tracing::info!(%addr, "Electrum server listening.");
+    serve(client, config, listener).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::hashes::{
+        Hash,
+        sha256d,
+    };
+    use crate::client::tests::{
+        create_synced_client,
+        load_blocks,
+        load_decoded_blocks,
+    };
+
+    #[test]
+    fn headers() {
+        let client = create_synced_client(10);
+        let blocks = load_decoded_blocks(10);
+        let mut session = Session::default();
+        let header = call(&client, &mut session, "blockchain.block.header", &json!([3])).unwrap();
+        assert_eq!(header, json!(hex::encode(bitcoin::consensus::serialize(&blocks[3].header))));
+        assert_eq!(call(&client, &mut session, "blockchain.block.header", &json!([10])).unwrap_err().code, BAD_REQUEST);
+        assert_eq!(call(&client, &mut session, "blockchain.block.header", &json!([3, 5])).unwrap_err().code, BAD_REQUEST);
+        let headers = call(&client, &mut session, "blockchain.block.headers", &json!([8, 5])).unwrap();
+        assert_eq!(headers["count"], 2);
+        assert_eq!(headers["hex"].as_str().unwrap().len(), 2 * 80 * 2);
+        assert_eq!(call(&client, &mut session, "blockchain.block.headers", &json!([20, 5])).unwrap()["count"], 0);
+        // Subscription.
+        assert_eq!(poll_tip(&client, &mut session), None);
+        let tip = call(&client, &mut session, "blockchain.headers.subscribe", &json!([])).unwrap();
+        assert_eq!(tip["height"], 9);
+        assert_eq!(poll_tip(&client, &mut session), None);
+        client.add_block(10, load_blocks()[10].clone(), None);
+        assert_eq!(poll_tip(&client, &mut session).unwrap()["params"][0]["height"], 10);
+        assert_eq!(poll_tip(&client, &mut session), None);
+    }
+
+    #[test]
+    fn merkle() {
+        let client = create_synced_client(547);
+        let blocks = load_decoded_blocks(547);
+        let mut session = Session::default();
+        let block = &blocks[546];
+        let tx_hashes = block.txdata.iter().map(|tx| tx.compute_txid().to_byte_array()).collect::<Vec<[u8; 32]>>();
+        for pos in 0..tx_hashes.len() {
+            let txid = block.txdata[pos].compute_txid().to_string();
+            let result = call(&client, &mut session, "blockchain.transaction.get_merkle", &json!([txid, 546])).unwrap();
+            assert_eq!(result["pos"], pos);
+            // Fold the branch back into the merkle root.
+            let mut hash = tx_hashes[pos];
+            let mut index = pos;
+            for sibling in merkle_branch(&tx_hashes, pos).unwrap() {
+                let pair = if index % 2 == 0 { [hash, sibling] } else { [sibling, hash] };
+                hash = sha256d::Hash::hash(&pair.concat()).to_byte_array();
+                index /= 2;
+            }
+            assert_eq!(hash, block.header.merkle_root.to_byte_array());
+            let result = call(&client, &mut session, "blockchain.transaction.id_from_pos", &json!([546, pos, true])).unwrap();
+            assert_eq!(result["tx_hash"], txid);
+        }
+        let txid = block.txdata[0].compute_txid().to_string();
+        assert_eq!(call(&client, &mut session, "blockchain.transaction.get_merkle", &json!([txid, 545])).unwrap_err().code, BAD_REQUEST);
+    }
+
+    #[test]
+    fn transactions() {
+        let client = create_synced_client(10);
+        let blocks = load_decoded_blocks(10);
+        let mut session = Session::default();
+        let tx = &blocks[5].txdata[0];
+        let txid = tx.compute_txid().to_string();
+        let result = call(&client, &mut session, "blockchain.transaction.get", &json!([txid])).unwrap();
+        assert_eq!(result, json!(hex::encode(bitcoin::consensus::serialize(tx))));
+        let result = call(&client, &mut session, "blockchain.transaction.get", &json!([txid, true])).unwrap();
+        assert_eq!(result["confirmations"], 5);
+        assert_eq!(call(&client, &mut session, "blockchain.transaction.get", &json!(["00".repeat(32)])).unwrap_err().code, DAEMON_ERROR);
+        assert_eq!(call(&client, &mut session, "blockchain.transaction.get", &json!(["zz"])).unwrap_err().code, BAD_REQUEST);
+        assert_eq!(call(&client, &mut session, "blockchain.scripthash.get_balance", &json!(["00".repeat(32)])).unwrap_err().code, BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn tcp() {
+        let client = create_synced_client(10);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(client, ElectrumConfig::default(), listener));
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"{\"jsonrpc\":\"2.0\",\"id\":0,\"method\":\"server.version\",\"params\":[\"test\",\"1.4\"]}\n").await.unwrap();
+        let reply = serde_json::from_str::<Value>(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["result"][1], PROTOCOL_VERSION);
+        writer.write_all(b"[{\"id\":1,\"method\":\"server.ping\"},{\"id\":2,\"method\":\"unknown\"}]\n").await.unwrap();
+        let reply = serde_json::from_str::<Value>(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply[0]["id"], 1);
+        assert_eq!(reply[1]["error"]["code"], METHOD_NOT_FOUND);
+        // Lines split across a tip poll are not lost.
+        writer.write_all(b"{\"id\":3,\"method\":").await.unwrap();
+        tokio::time::sleep(TIP_POLL_INTERVAL + Duration::from_millis(200)).await;
+        writer.write_all(b"\"server.ping\"}\n").await.unwrap();
+        let reply = serde_json::from_str::<Value>(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["id"], 3);
+        assert_eq!(reply["result"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let client = create_synced_client(10);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = ElectrumConfig {
+            max_connections: 1,
+            max_batch_size: 2,
+            budget: Budget { rate: 0.0, burst: 3.0 },
+        };
+        tokio::spawn(serve(client, config, listener));
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        // Oversized batches are rejected without consuming the budget.
+        writer.write_all(b"[{\"id\":0,\"method\":\"server.ping\"},{\"id\":1,\"method\":\"server.ping\"},{\"id\":2,\"method\":\"server.ping\"}]\n").await.unwrap();
+        let reply = serde_json::from_str::<Value>(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+        // Every request of a batch consumes a token.
+        writer.write_all(b"[{\"id\":3,\"method\":\"server.ping\"},{\"id\":4,\"method\":\"server.ping\"}]\n").await.unwrap();
+        let reply = serde_json::from_str::<Value>(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply[1]["result"], Value::Null);
+        writer.write_all(b"[{\"id\":5,\"method\":\"server.ping\"},{\"id\":6,\"method\":\"server.ping\"}]\n").await.unwrap();
+        let reply = serde_json::from_str::<Value>(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply[0]["result"], Value::Null);
+        assert_eq!(reply[1]["error"]["code"], EXCESSIVE_RESOURCE_USAGE);
+        // Connections beyond the limit are closed.
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+}
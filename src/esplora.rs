@@ -0,0 +1,546 @@
+/*
+ * Subset of the Esplora HTTP API (https://github.com/Blockstream/esplora/blob/master/API.md).
+ * Address and scripthash routes are not served, as the mirror has no address index.
+ */
+
+use std::collections::HashMap;
+use axum::{
+    Router,
+    routing::get,
+    extract::{
+        Path,
+        State,
+    },
+    http::StatusCode,
+    response::{
+        Response,
+        IntoResponse,
+    },
+    Json,
+};
+use serde_json::{
+    json,
+    Map,
+    Value,
+};
+use bitcoin::{
+    Address,
+    Amount,
+    MerkleBlock,
+    Script,
+    Transaction,
+    Txid,
+    block::{
+        Block,
+        Header,
+    },
+    consensus::Decodable,
+    hashes::Hash,
+};
+
+use crate::{
+    client::Client,
+    json::script_type,
+    merkle::{
+        hash_to_string,
+        merkle_branch,
+    },
+    server::with_confirmations,
+};
+
+// Number of transactions per page of `/block/:hash/txs`.
+const BLOCK_TXS_PAGE_SIZE: usize = 25;
+// Number of blocks returned by `/blocks`.
+const BLOCKS_PAGE_SIZE: u32 = 10;
+
+// Status code and message of a failed request.
+type EsploraError = (StatusCode, String);
+
+fn parse_hash(hash_str: &str) -> Result<[u8; 32], EsploraError> {
+    match hex::decode(hash_str).ok().and_then(|hash| <[u8; 32]>::try_from(hash).ok()) {
+        Some(mut hash) => {
+            hash.reverse();
+            Ok(hash)
+        },
+        None => Err((StatusCode::BAD_REQUEST, "Invalid hex string".to_string())),
+    }
+}
+
+fn parse_height(height: &str) -> Result<u32, EsploraError> {
+    height.parse::<u32>().map_err(|_| (StatusCode::BAD_REQUEST, "Invalid height".to_string()))
+}
+
+fn block_not_found() -> EsploraError {
+    (StatusCode::NOT_FOUND, "Block not found".to_string())
+}
+
+fn tx_not_found() -> EsploraError {
+    (StatusCode::NOT_FOUND, "Transaction not found".to_string())
+}
+
+// Script type names used by Esplora.
+fn esplora_script_type(script: &Script) -> &'static str {
+    if script.is_empty() {
+        return "empty";
+    }
+    if script.is_op_return() {
+        return "op_return";
+    }
+    match script_type(script) {
+        "pubkey" => "p2pk",
+        "pubkeyhash" => "p2pkh",
+        "scripthash" => "p2sh",
+        "witness_v0_keyhash" => "v0_p2wpkh",
+        "witness_v0_scripthash" => "v0_p2wsh",
+        "witness_v1_taproot" => "v1_p2tr",
+        "multisig" => "multisig",
+        "anchor" => "anchor",
+        _ => "unknown",
+    }
+}
+
+fn output_to_json(script: &Script, value: Amount, client: &Client) -> Value {
+    let mut result = Map::new();
+    result.insert("scriptpubkey".to_string(), json!(hex::encode(script.as_bytes())));
+    result.insert("scriptpubkey_asm".to_string(), json!(script.to_asm_string()));
+    result.insert("scriptpubkey_type".to_string(), json!(esplora_script_type(script)));
+    if let Ok(address) = Address::from_script(script, client.get_network()) {
+        result.insert("scriptpubkey_address".to_string(), json!(address.to_string()));
+    }
+    result.insert("value".to_string(), json!(value.to_sat()));
+    Value::Object(result)
+}
+
+// Confirmation status of a transaction.
+fn tx_status(client: &Client, tx_hash: &[u8; 32]) -> Value {
+    let block = client.get_block_hash_by_transaction(tx_hash).and_then(|block_hash| {
+        let height = client.get_block_height_by_hash(&block_hash)?;
+        let block_time = client.get_block_time_by_height(height)?;
+        Some((block_hash, height, block_time.time))
+    });
+    match block {
+        Some((block_hash, height, time)) => json!({
+            "confirmed": true,
+            "block_height": height,
+            "block_hash": hash_to_string(&block_hash),
+            "block_time": time,
+        }),
+        None => json!({
+            "confirmed": false,
+        }),
+    }
+}
+
+// Read the transactions spent by the given ones at once.
+fn get_parent_transactions(client: &Client, txs: &[Transaction]) -> HashMap<Txid, Transaction> {
+    let mut txids = txs.iter()
+        .filter(|tx| !tx.is_coinbase())
+        .flat_map(|tx| tx.input.iter().map(|input| input.previous_output.txid))
+        .collect::<Vec<Txid>>();
+    txids.sort();
+    txids.dedup();
+    let tx_hashes = txids.iter().map(|txid| txid.to_byte_array()).collect::<Vec<[u8; 32]>>();
+    txids.into_iter().zip(client.get_transactions(&tx_hashes)).filter_map(|(txid, tx)| {
+        Some((txid, Transaction::consensus_decode(&mut tx?.as_slice()).unwrap()))
+    }).collect()
+}
+
+/*
+ * Transaction in the Esplora format, with the prevouts and the fee.
+ * @param parents Transactions spent by `tx`, as returned by `get_parent_transactions`.
+ */
+fn tx_to_json(client: &Client, tx: &Transaction, parents: &HashMap<Txid, Transaction>) -> Value {
+    let prevouts = tx.input.iter().map(|input| {
+        if tx.is_coinbase() {
+            return None;
+        }
+        parents.get(&input.previous_output.txid)?.output.get(input.previous_output.vout as usize).cloned()
+    }).collect::<Vec<_>>();
+    let vin = tx.input.iter().zip(prevouts.iter()).map(|(input, prevout)| {
+        let mut result = Map::new();
+        result.insert("txid".to_string(), json!(input.previous_output.txid.to_string()));
+        result.insert("vout".to_string(), json!(input.previous_output.vout));
+        result.insert("prevout".to_string(), match prevout {
+            Some(prevout) => output_to_json(&prevout.script_pubkey, prevout.value, client),
+            None => Value::Null,
+        });
+        result.insert("scriptsig".to_string(), json!(hex::encode(input.script_sig.as_bytes())));
+        result.insert("scriptsig_asm".to_string(), json!(input.script_sig.to_asm_string()));
+        if !input.witness.is_empty() {
+            result.insert("witness".to_string(), json!(input.witness.iter().map(hex::encode).collect::<Vec<String>>()));
+        }
+        result.insert("is_coinbase".to_string(), json!(tx.is_coinbase()));
+        result.insert("sequence".to_string(), json!(input.sequence.to_consensus_u32()));
+        Value::Object(result)
+    }).collect::<Vec<Value>>();
+    let vout = tx.output.iter().map(|output| output_to_json(&output.script_pubkey, output.value, client)).collect::<Vec<Value>>();
+    let fee = match tx.is_coinbase() {
+        true => Some(0),
+        false => prevouts.iter()
+            .map(|prevout| prevout.as_ref().map(|prevout| prevout.value.to_sat()))
+            .sum::<Option<u64>>()
+            .map(|input_value| input_value.saturating_sub(tx.output.iter().map(|output| output.value.to_sat()).sum())),
+    };
+    let tx_hash = tx.compute_txid().to_byte_array();
+    json!({
+        "txid": tx.compute_txid().to_string(),
+        "version": tx.version.0,
+        "locktime": tx.lock_time.to_consensus_u32(),
+        "vin": vin,
+        "vout": vout,
+        "size": tx.total_size(),
+        "weight": tx.weight().to_wu(),
+        "fee": fee,
+        "status": tx_status(client, &tx_hash),
+    })
+}
+
+fn block_to_json(client: &Client, block_hash: &[u8; 32]) -> Option<Value> {
+    let height = client.get_block_height_by_hash(block_hash)?;
+    let block_time = client.get_block_time_by_height(height)?;
+    let block = client.get_block(block_hash)?;
+    let block = Block::consensus_decode(&mut block.as_slice()).unwrap();
+    let previous_block_hash = match height {
+        0 => Value::Null,
+        _ => json!(block.header.prev_blockhash.to_string()),
+    };
+    Some(json!({
+        "id": block.block_hash().to_string(),
+        "height": height,
+        "version": block.header.version.to_consensus(),
+        "timestamp": block.header.time,
+        "tx_count": block.txdata.len(),
+        "size": block.total_size(),
+        "weight": block.weight().to_wu(),
+        "merkle_root": block.header.merkle_root.to_string(),
+        "previousblockhash": previous_block_hash,
+        "mediantime": block_time.median_time,
+        "nonce": block.header.nonce,
+        "bits": block.header.bits.to_consensus(),
+        "difficulty": block.header.difficulty_float(),
+    }))
+}
+
+fn get_tip_height(client: &Client) -> Result<u32, EsploraError> {
+    match client.get_next_block_height() {
+        0 => Err((StatusCode::SERVICE_UNAVAILABLE, "No blocks synced yet".to_string())),
+        next_block_height => Ok(next_block_height - 1),
+    }
+}
+
+fn get_tx_hashes(client: &Client, block_hash: &[u8; 32]) -> Result<Vec<[u8; 32]>, EsploraError> {
+    client.get_block_transaction_hashes(block_hash).ok_or_else(block_not_found)
+}
+
+async fn handle_block(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    let block_hash = match parse_hash(&path) {
+        Ok(block_hash) => block_hash,
+        Err(e) => return e.into_response(),
+    };
+    match block_to_json(&client, &block_hash) {
+        Some(block) => with_confirmations(Json(block).into_response(), &client, client.get_block_height_by_hash(&block_hash)),
+        None => block_not_found().into_response(),
+    }
+}
+
+async fn handle_block_header(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    let block_hash = match parse_hash(&path) {
+        Ok(block_hash) => block_hash,
+        Err(e) => return e.into_response(),
+    };
+    match client.get_block_header(&block_hash) {
+        Some(header) => with_confirmations(hex::encode(header).into_response(), &client, client.get_block_height_by_hash(&block_hash)),
+        None => block_not_found().into_response(),
+    }
+}
+
+async fn handle_block_status(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    let block_hash = match parse_hash(&path) {
+        Ok(block_hash) => block_hash,
+        Err(e) => return e.into_response(),
+    };
+    let position = match client.get_chain_position(&block_hash) {
+        Some(position) => position,
+        None => return Json(json!({ "in_best_chain": false })).into_response(),
+    };
+    Json(json!({
+        "in_best_chain": true,
+        "height": position.height,
+        "next_best": position.next_block_hash.map(|next_block_hash| next_block_hash.to_string()),
+    })).into_response()
+}
+
+async fn handle_block_txids(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    let block_hash = match parse_hash(&path) {
+        Ok(block_hash) => block_hash,
+        Err(e) => return e.into_response(),
+    };
+    let tx_hashes = match get_tx_hashes(&client, &block_hash) {
+        Ok(tx_hashes) => tx_hashes,
+        Err(e) => return e.into_response(),
+    };
+    let txids = tx_hashes.iter().map(hash_to_string).collect::<Vec<String>>();
+    with_confirmations(Json(txids).into_response(), &client, client.get_block_height_by_hash(&block_hash))
+}
+
+async fn handle_block_txid(client: State<Client>, Path((hash, index)): Path<(String, String)>) -> impl IntoResponse {
+    let block_hash = match parse_hash(&hash) {
+        Ok(block_hash) => block_hash,
+        Err(e) => return e.into_response(),
+    };
+    let index = match index.parse::<usize>() {
+        Ok(index) => index,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid index".to_string()).into_response(),
+    };
+    let tx_hashes = match get_tx_hashes(&client, &block_hash) {
+        Ok(tx_hashes) => tx_hashes,
+        Err(e) => return e.into_response(),
+    };
+    match tx_hashes.get(index) {
+        Some(tx_hash) => with_confirmations(hash_to_string(tx_hash).into_response(), &client, client.get_block_height_by_hash(&block_hash)),
+        None => (StatusCode::NOT_FOUND, "Transaction index out of range".to_string()).into_response(),
+    }
+}
+
+fn block_txs_response(client: &Client, hash: &str, start_index: usize) -> Response {
+    let block_hash = match parse_hash(hash) {
+        Ok(block_hash) => block_hash,
+        Err(e) => return e.into_response(),
+    };
+    if !start_index.is_multiple_of(BLOCK_TXS_PAGE_SIZE) {
+        return (StatusCode::BAD_REQUEST, format!("start index must be a multiplication of {}", BLOCK_TXS_PAGE_SIZE)).into_response();
+    }
+    let tx_hashes = match get_tx_hashes(client, &block_hash) {
+        Ok(tx_hashes) => tx_hashes,
+        Err(e) => return e.into_response(),
+    };
+    if start_index >= tx_hashes.len() {
+        return (StatusCode::NOT_FOUND, "start index out of range".to_string()).into_response();
+    }
+    let page = &tx_hashes[start_index..(start_index + BLOCK_TXS_PAGE_SIZE).min(tx_hashes.len())];
+    let mut txs = Vec::new();
+    for tx in client.get_transactions(page) {
+        match tx {
+            Some(tx) => txs.push(Transaction::consensus_decode(&mut tx.as_slice()).unwrap()),
+            None => return tx_not_found().into_response(),
+        }
+    }
+    let parents = get_parent_transactions(client, &txs);
+    let txs = txs.iter().map(|tx| tx_to_json(client, tx, &parents)).collect::<Vec<Value>>();
+    with_confirmations(Json(txs).into_response(), client, client.get_block_height_by_hash(&block_hash))
+}
+
+async fn handle_block_txs(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    block_txs_response(&client, &path, 0)
+}
+
+async fn handle_block_txs_from(client: State<Client>, Path((hash, start_index)): Path<(String, String)>) -> impl IntoResponse {
+    match start_index.parse::<usize>() {
+        Ok(start_index) => block_txs_response(&client, &hash, start_index),
+        Err(_) => (StatusCode::BAD_REQUEST, "Invalid start index".to_string()).into_response(),
+    }
+}
+
+async fn handle_block_raw(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    let block_hash = match parse_hash(&path) {
+        Ok(block_hash) => block_hash,
+        Err(e) => return e.into_response(),
+    };
+    match client.get_block(&block_hash) {
+        Some(block) => with_confirmations(block.into_response(), &client, client.get_block_height_by_hash(&block_hash)),
+        None => block_not_found().into_response(),
+    }
+}
+
+async fn handle_block_height(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    let height = match parse_height(&path) {
+        Ok(height) => height,
+        Err(e) => return e.into_response(),
+    };
+    if height >= client.get_next_block_height() {
+        return block_not_found().into_response();
+    }
+    match client.get_block_hash_by_height(height) {
+        Some(block_hash) => with_confirmations(hash_to_string(&block_hash).into_response(), &client, Some(height)),
+        None => block_not_found().into_response(),
+    }
+}
+
+fn blocks_response(client: &Client, start_height: Option<u32>) -> Response {
+    let tip_height = match get_tip_height(client) {
+        Ok(tip_height) => tip_height,
+        Err(e) => return e.into_response(),
+    };
+    let start_height = start_height.unwrap_or(tip_height).min(tip_height);
+    let mut blocks = Vec::new();
+    // Blocks are listed from the start height downwards.
+    for height in (start_height.saturating_sub(BLOCKS_PAGE_SIZE - 1)..=start_height).rev() {
+        let block = client.get_block_hash_by_height(height).and_then(|block_hash| block_to_json(client, &block_hash));
+        match block {
+            Some(block) => blocks.push(block),
+            None => return block_not_found().into_response(),
+        }
+    }
+    Json(blocks).into_response()
+}
+
+async fn handle_blocks(client: State<Client>) -> impl IntoResponse {
+    blocks_response(&client, None)
+}
+
+async fn handle_blocks_from(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    match parse_height(&path) {
+        Ok(start_height) => blocks_response(&client, Some(start_height)),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn handle_blocks_tip_height(client: State<Client>) -> impl IntoResponse {
+    match get_tip_height(&client) {
+        Ok(tip_height) => tip_height.to_string().into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn handle_blocks_tip_hash(client: State<Client>) -> impl IntoResponse {
+    let tip_height = match get_tip_height(&client) {
+        Ok(tip_height) => tip_height,
+        Err(e) => return e.into_response(),
+    };
+    match client.get_block_hash_by_height(tip_height) {
+        Some(block_hash) => hash_to_string(&block_hash).into_response(),
+        None => block_not_found().into_response(),
+    }
+}
+
+// Confirmed transactions, with a fallback to the mirrored mempool.
+fn get_tx(client: &Client, hash: &str) -> Result<([u8; 32], Vec<u8>), EsploraError> {
+    let tx_hash = parse_hash(hash)?;
+    match client.get_transaction(&tx_hash).or_else(|| client.get_mempool_transaction(&tx_hash)) {
+        Some(tx) => Ok((tx_hash, tx)),
+        None => Err(tx_not_found()),
+    }
+}
+
+fn tx_height(client: &Client, tx_hash: &[u8; 32]) -> Option<u32> {
+    client.get_block_hash_by_transaction(tx_hash).and_then(|block_hash| client.get_block_height_by_hash(&block_hash))
+}
+
+async fn handle_tx(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    let (tx_hash, tx) = match get_tx(&client, &path) {
+        Ok(tx) => tx,
+        Err(e) => return e.into_response(),
+    };
+    let tx = Transaction::consensus_decode(&mut tx.as_slice()).unwrap();
+    let parents = get_parent_transactions(&client, std::slice::from_ref(&tx));
+    with_confirmations(Json(tx_to_json(&client, &tx, &parents)).into_response(), &client, tx_height(&client, &tx_hash))
+}
+
+async fn handle_tx_hex(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    match get_tx(&client, &path) {
+        Ok((tx_hash, tx)) => with_confirmations(hex::encode(tx).into_response(), &client, tx_height(&client, &tx_hash)),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn handle_tx_raw(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    match get_tx(&client, &path) {
+        Ok((tx_hash, tx)) => with_confirmations(tx.into_response(), &client, tx_height(&client, &tx_hash)),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn handle_tx_status(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    match get_tx(&client, &path) {
+        Ok((tx_hash, _)) => with_confirmations(Json(tx_status(&client, &tx_hash)).into_response(), &client, tx_height(&client, &tx_hash)),
+        Err(e) => e.into_response(),
+    }
+}
+
+// Position of a confirmed transaction in its block.
+struct TxPosition {
+    block_hash: [u8; 32],
+    tx_hashes: Vec<[u8; 32]>,
+    pos: usize,
+}
+
+fn get_tx_position(client: &Client, hash: &str) -> Result<TxPosition, EsploraError> {
+    let tx_hash = parse_hash(hash)?;
+    let block_hash = match client.get_block_hash_by_transaction(&tx_hash) {
+        Some(block_hash) => block_hash,
+        None => return Err((StatusCode::NOT_FOUND, "Transaction not found or is unconfirmed".to_string())),
+    };
+    let tx_hashes = get_tx_hashes(client, &block_hash)?;
+    match tx_hashes.iter().position(|hash| *hash == tx_hash) {
+        Some(pos) => Ok(TxPosition {
+            block_hash,
+            tx_hashes,
+            pos,
+        }),
+        None => Err(tx_not_found()),
+    }
+}
+
+async fn handle_tx_merkle_proof(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    let TxPosition { block_hash, tx_hashes, pos } = match get_tx_position(&client, &path) {
+        Ok(position) => position,
+        Err(e) => return e.into_response(),
+    };
+    let height = client.get_block_height_by_hash(&block_hash);
+    let merkle = match merkle_branch(&tx_hashes, pos) {
+        Some(merkle) => merkle.iter().map(hash_to_string).collect::<Vec<String>>(),
+        None => return tx_not_found().into_response(),
+    };
+    with_confirmations(Json(json!({
+        "block_height": height,
+        "merkle": merkle,
+        "pos": pos,
+    })).into_response(), &client, height)
+}
+
+async fn handle_tx_merkleblock_proof(client: State<Client>, path: Path<String>) -> impl IntoResponse {
+    let TxPosition { block_hash, tx_hashes, pos } = match get_tx_position(&client, &path) {
+        Ok(position) => position,
+        Err(e) => return e.into_response(),
+    };
+    let header = match client.get_block_header(&block_hash) {
+        Some(header) => Header::consensus_decode(&mut header.as_slice()).unwrap(),
+        None => return block_not_found().into_response(),
+    };
+    let txids = tx_hashes.iter().map(|tx_hash| Txid::from_byte_array(*tx_hash)).collect::<Vec<_>>();
+    let txid = txids[pos];
+    let merkle_block = MerkleBlock::from_header_txids_with_predicate(&header, &txids, |t| *t == txid);
+    let proof = bitcoin::consensus::serialize(&merkle_block);
+    with_confirmations(hex::encode(proof).into_response(), &client, client.get_block_height_by_hash(&block_hash))
+}
+
+async fn handle_mempool_txids(client: State<Client>) -> impl IntoResponse {
+    let txids = client.get_mempool_txids().iter().map(hash_to_string).collect::<Vec<String>>();
+    Json(txids)
+}
+
+// Routes relative to the Esplora prefix (e.g. `/api`).
+pub fn router<S>(client: Client) -> Router<S> {
+    Router::new()
+        .route("/block/:hash", get(handle_block))
+        .route("/block/:hash/header", get(handle_block_header))
+        .route("/block/:hash/status", get(handle_block_status))
+        .route("/block/:hash/txids", get(handle_block_txids))
+        .route("/block/:hash/txid/:index", get(handle_block_txid))
+        .route("/block/:hash/txs", get(handle_block_txs))
+        .route("/block/:hash/txs/:start_index", get(handle_block_txs_from))
+        .route("/block/:hash/raw", get(handle_block_raw))
+        .route("/block-height/:height", get(handle_block_height))
+        .route("/blocks", get(handle_blocks))
+        .route("/blocks/:start_height", get(handle_blocks_from))
+        .route("/blocks/tip/height", get(handle_blocks_tip_height))
+        .route("/blocks/tip/hash", get(handle_blocks_tip_hash))
+        .route("/tx/:txid", get(handle_tx))
+        .route("/tx/:txid/hex", get(handle_tx_hex))
+        .route("/tx/:txid/raw", get(handle_tx_raw))
+        .route("/tx/:txid/status", get(handle_tx_status))
+        .route("/tx/:txid/merkle-proof", get(handle_tx_merkle_proof))
+        .route("/tx/:txid/merkleblock-proof", get(handle_tx_merkleblock_proof))
+        .route("/mempool/txids", get(handle_mempool_txids))
+        .with_state(client)
+}
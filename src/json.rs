@@ -0,0 +1,465 @@
+
+/*
+ * JSON representations compatible with the REST interface of Bitcoin Core.
+ */
+
+use serde_json::{
+    json,
+    Map,
+    Value,
+};
+use bitcoin::{
+    Address,
+    Amount,
+    BlockHash,
+    Network,
+    Script,
+    Transaction,
+    Txid,
+    VarInt,
+    block::{
+        Block,
+        Header,
+    },
+    consensus::Encodable,
+    hashes::Hash,
+    key::XOnlyPublicKey,
+    opcodes::Opcode,
+    pow::Work,
+    script::Instruction,
+};
+
+const MAX_SCRIPT_SIZE: usize = 10000;
+
+// Position of a block in the active chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainPosition {
+    pub height: u32,
+    pub confirmations: u32,
+    pub median_time: u32,
+    pub chain_work: Work,
+    pub n_tx: usize,
+    pub next_block_hash: Option<BlockHash>,
+}
+
+fn amount_to_json(amount: Amount) -> Value {
+    json!(amount.to_btc())
+}
+
+// Decode a minimally encoded script number (up to 4 bytes).
+fn script_num(bytes: &[u8]) -> i64 {
+    if bytes.is_empty() {
+        return 0;
+    }
+    let mut result = 0i64;
+    for (i, byte) in bytes.iter().enumerate() {
+        result |= (*byte as i64) << (8 * i);
+    }
+    // The most significant bit of the last byte is the sign bit.
+    let sign_bit = 0x80i64 << (8 * (bytes.len() - 1));
+    if result & sign_bit != 0 {
+        return -(result & !sign_bit);
+    }
+    result
+}
+
+fn opcode_name(opcode: Opcode) -> String {
+    match opcode.to_u8() {
+        0x4f => "-1".to_string(),
+        n @ 0x51..=0x60 => (n - 0x50).to_string(),
+        0xb1 => "OP_CHECKLOCKTIMEVERIFY".to_string(),
+        0xb2 => "OP_CHECKSEQUENCEVERIFY".to_string(),
+        0xbb..=0xfe => "OP_UNKNOWN".to_string(),
+        _ => opcode.to_string(),
+    }
+}
+
+// Strict DER signature encoding check as defined by BIP66.
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    if sig.len() < 9 || sig.len() > 73 {
+        return false;
+    }
+    if sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0x00 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0x00 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+    true
+}
+
+fn sighash_type_name(sighash_type: u8) -> Option<&'static str> {
+    match sighash_type {
+        0x01 => Some("ALL"),
+        0x81 => Some("ALL|ANYONECANPAY"),
+        0x02 => Some("NONE"),
+        0x82 => Some("NONE|ANYONECANPAY"),
+        0x03 => Some("SINGLE"),
+        0x83 => Some("SINGLE|ANYONECANPAY"),
+        _ => None,
+    }
+}
+
+/*
+ * Disassemble a script in the same format as Bitcoin Core.
+ * @param attempt_sighash_decode Decode the sighash type of signatures (used for scriptSig).
+ */
+pub fn script_to_asm(script: &Script, attempt_sighash_decode: bool) -> String {
+    let is_unspendable = script.as_bytes().first() == Some(&0x6a) || script.len() > MAX_SCRIPT_SIZE;
+    let mut parts = Vec::new();
+    for instruction in script.instructions() {
+        match instruction {
+            Ok(Instruction::PushBytes(push_bytes)) => {
+                let data = push_bytes.as_bytes();
+                if data.len() <= 4 {
+                    parts.push(script_num(data).to_string());
+                    continue;
+                }
+                if attempt_sighash_decode && !is_unspendable && is_valid_signature_encoding(data) {
+                    if let Some(name) = sighash_type_name(data[data.len() - 1]) {
+                        parts.push(format!("{}[{}]", hex::encode(&data[..data.len() - 1]), name));
+                        continue;
+                    }
+                }
+                parts.push(hex::encode(data));
+            },
+            Ok(Instruction::Op(opcode)) => parts.push(opcode_name(opcode)),
+            Err(_) => {
+                parts.push("[error]".to_string());
+                break;
+            },
+        }
+    }
+    parts.join(" ")
+}
+
+fn is_push_only(script: &Script) -> bool {
+    script.instructions().all(|instruction| match instruction {
+        Ok(Instruction::PushBytes(_)) => true,
+        Ok(Instruction::Op(opcode)) => opcode.to_u8() <= 0x60,
+        Err(_) => false,
+    })
+}
+
+// Script type names used by Bitcoin Core.
+pub fn script_type(script: &Script) -> &'static str {
+    let bytes = script.as_bytes();
+    if script.is_p2sh() {
+        return "scripthash";
+    }
+    if let Some(version) = script.witness_version() {
+        let program_len = bytes.len() - 2;
+        return match (version.to_num(), program_len) {
+            (0, 20) => "witness_v0_keyhash",
+            (0, 32) => "witness_v0_scripthash",
+            (0, _) => "nonstandard",
+            (1, 32) => "witness_v1_taproot",
+            (1, 2) if bytes[2..] == [0x4e, 0x73] => "anchor",
+            _ => "witness_unknown",
+        };
+    }
+    if bytes.first() == Some(&0x6a) && is_push_only(Script::from_bytes(&bytes[1..])) {
+        return "nulldata";
+    }
+    if script.is_p2pk() {
+        return "pubkey";
+    }
+    if script.is_p2pkh() {
+        return "pubkeyhash";
+    }
+    if script.is_multisig() {
+        return "multisig";
+    }
+    "nonstandard"
+}
+
+// Checksum appended to output descriptors (BIP380).
+fn descriptor_checksum(descriptor: &str) -> String {
+    const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+    const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    const GENERATOR: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+    let polymod = |c: u64, value: u64| {
+        let c0 = c >> 35;
+        let mut c = ((c & 0x7ffffffff) << 5) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (c0 >> i) & 1 == 1 {
+                c ^= generator;
+            }
+        }
+        c
+    };
+    let mut c = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET.find(ch).expect("Invalid descriptor character") as u64;
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    (0..8).map(|i| CHECKSUM_CHARSET[((c >> (5 * (7 - i))) & 31) as usize] as char).collect()
+}
+
+fn multisig_descriptor(script: &Script) -> Option<String> {
+    let instructions = script.instructions().collect::<Result<Vec<Instruction>, _>>().ok()?;
+    let required = match instructions.first()? {
+        Instruction::Op(opcode) => opcode.to_u8().checked_sub(0x50)?,
+        Instruction::PushBytes(_) => return None,
+    };
+    let keys = instructions[1..instructions.len() - 2].iter().map(|instruction| match instruction {
+        Instruction::PushBytes(key) => Some(hex::encode(key.as_bytes())),
+        Instruction::Op(_) => None,
+    }).collect::<Option<Vec<String>>>()?;
+    Some(format!("multi({},{})", required, keys.join(",")))
+}
+
+/*
+ * Output descriptor of a script, as inferred by Bitcoin Core without any key information:
+ * `pk`, `multi` and `rawtr` for the scripts exposing their keys, `addr` for the other ones with an address
+ * and `raw` for the rest.
+ */
+fn script_descriptor(script: &Script, network: Network) -> String {
+    let bytes = script.as_bytes();
+    let descriptor = match script_type(script) {
+        "pubkey" => Some(format!("pk({})", hex::encode(&bytes[1..bytes.len() - 1]))),
+        "multisig" => multisig_descriptor(script),
+        "witness_v1_taproot" => XOnlyPublicKey::from_slice(&bytes[2..]).ok().map(|_| format!("rawtr({})", hex::encode(&bytes[2..]))),
+        _ => None,
+    };
+    let descriptor = descriptor
+        .or_else(|| Address::from_script(script, network).ok().map(|address| format!("addr({})", address)))
+        .unwrap_or_else(|| format!("raw({})", hex::encode(bytes)));
+    let checksum = descriptor_checksum(&descriptor);
+    format!("{}#{}", descriptor, checksum)
+}
+
+pub fn script_pubkey_to_json(script: &Script, network: Network) -> Value {
+    let mut result = Map::new();
+    result.insert("asm".to_string(), json!(script_to_asm(script, false)));
+    result.insert("desc".to_string(), json!(script_descriptor(script, network)));
+    result.insert("hex".to_string(), json!(hex::encode(script.as_bytes())));
+    if let Ok(address) = Address::from_script(script, network) {
+        result.insert("address".to_string(), json!(address.to_string()));
+    }
+    result.insert("type".to_string(), json!(script_type(script)));
+    Value::Object(result)
+}
+
+pub fn tx_to_json(tx: &Transaction, network: Network) -> Value {
+    let vin = tx.input.iter().map(|input| {
+        let mut result = Map::new();
+        if tx.is_coinbase() {
+            result.insert("coinbase".to_string(), json!(hex::encode(input.script_sig.as_bytes())));
+        } else {
+            result.insert("txid".to_string(), json!(input.previous_output.txid.to_string()));
+            result.insert("vout".to_string(), json!(input.previous_output.vout));
+            result.insert("scriptSig".to_string(), json!({
+                "asm": script_to_asm(&input.script_sig, true),
+                "hex": hex::encode(input.script_sig.as_bytes()),
+            }));
+        }
+        if !input.witness.is_empty() {
+            let witness = input.witness.iter().map(hex::encode).collect::<Vec<String>>();
+            result.insert("txinwitness".to_string(), json!(witness));
+        }
+        result.insert("sequence".to_string(), json!(input.sequence.to_consensus_u32()));
+        Value::Object(result)
+    }).collect::<Vec<Value>>();
+    let vout = tx.output.iter().enumerate().map(|(n, output)| {
+        json!({
+            "value": amount_to_json(output.value),
+            "n": n,
+            "scriptPubKey": script_pubkey_to_json(&output.script_pubkey, network),
+        })
+    }).collect::<Vec<Value>>();
+    let mut tx_vec = Vec::new();
+    tx.consensus_encode(&mut tx_vec).unwrap();
+    json!({
+        "txid": tx.compute_txid().to_string(),
+        "hash": tx.compute_wtxid().to_string(),
+        "version": tx.version.0,
+        "size": tx.total_size(),
+        "vsize": tx.vsize(),
+        "weight": tx.weight().to_wu(),
+        "locktime": tx.lock_time.to_consensus_u32(),
+        "vin": vin,
+        "vout": vout,
+        "hex": hex::encode(tx_vec),
+    })
+}
+
+pub fn header_to_json(header: &Header, position: &ChainPosition) -> Value {
+    let mut result = Map::new();
+    result.insert("hash".to_string(), json!(header.block_hash().to_string()));
+    result.insert("confirmations".to_string(), json!(position.confirmations));
+    result.insert("height".to_string(), json!(position.height));
+    result.insert("version".to_string(), json!(header.version.to_consensus()));
+    result.insert("versionHex".to_string(), json!(format!("{:08x}", header.version.to_consensus())));
+    result.insert("merkleroot".to_string(), json!(header.merkle_root.to_string()));
+    result.insert("time".to_string(), json!(header.time));
+    result.insert("mediantime".to_string(), json!(position.median_time));
+    result.insert("nonce".to_string(), json!(header.nonce));
+    result.insert("bits".to_string(), json!(format!("{:08x}", header.bits.to_consensus())));
+    result.insert("difficulty".to_string(), json!(header.difficulty_float()));
+    result.insert("chainwork".to_string(), json!(hex::encode(position.chain_work.to_be_bytes())));
+    result.insert("nTx".to_string(), json!(position.n_tx));
+    if position.height > 0 {
+        result.insert("previousblockhash".to_string(), json!(header.prev_blockhash.to_string()));
+    }
+    if let Some(next_block_hash) = position.next_block_hash {
+        result.insert("nextblockhash".to_string(), json!(next_block_hash.to_string()));
+    }
+    Value::Object(result)
+}
+
+/*
+ * @param tx_details Include decoded transactions instead of txids.
+ */
+pub fn block_to_json(block: &Block, position: &ChainPosition, network: Network, tx_details: bool) -> Value {
+    let mut result = header_to_json(&block.header, position);
+    let txs = block.txdata.iter().map(|tx| {
+        if tx_details {
+            tx_to_json(tx, network)
+        } else {
+            json!(tx.compute_txid().to_string())
+        }
+    }).collect::<Vec<Value>>();
+    let result_map = result.as_object_mut().unwrap();
+    let stripped_size = 80 + VarInt::from(block.txdata.len()).size() + block.txdata.iter().map(|tx| tx.base_size()).sum::<usize>();
+    result_map.insert("strippedsize".to_string(), json!(stripped_size));
+    result_map.insert("size".to_string(), json!(block.total_size()));
+    result_map.insert("weight".to_string(), json!(block.weight().to_wu()));
+    result_map.insert("tx".to_string(), json!(txs));
+    result
+}
+
+// Block without transaction details, built from the header and the txids only.
+pub fn block_txids_to_json(header: &Header, position: &ChainPosition, tx_hashes: &[[u8; 32]]) -> Value {
+    let mut result = header_to_json(header, position);
+    let txids = tx_hashes.iter().map(|tx_hash| Txid::from_byte_array(*tx_hash).to_string()).collect::<Vec<String>>();
+    result.as_object_mut().unwrap().insert("tx".to_string(), json!(txids));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::{
+        ScriptBuf,
+        consensus::Decodable,
+    };
+
+    #[test]
+    fn asm() {
+        // P2PKH.
+        let script = ScriptBuf::from_hex("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac").unwrap();
+        assert_eq!(script_to_asm(&script, false), "OP_DUP OP_HASH160 62e907b15cbf27d5425399ebf6f0fb50ebb88f18 OP_EQUALVERIFY OP_CHECKSIG");
+        // Small numbers.
+        let script = ScriptBuf::from_hex("004f516001ff").unwrap();
+        assert_eq!(script_to_asm(&script, false), "0 -1 1 16 -127");
+        // Truncated push.
+        let script = ScriptBuf::from_hex("5105aabb").unwrap();
+        assert_eq!(script_to_asm(&script, false), "1 [error]");
+        // Signature with sighash type.
+        let script = ScriptBuf::from_hex("47304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901").unwrap();
+        assert_eq!(script_to_asm(&script, true), "304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d09[ALL]");
+        assert_eq!(script_to_asm(&script, false), "304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901");
+    }
+
+    #[test]
+    fn types() {
+        let types = [
+            ("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac", "pubkeyhash"),
+            ("a914748284390f9e263a4b766a75d0633c50426eb87587", "scripthash"),
+            ("0014751e76e8199196d454941c45d1b3a323f1433bd6", "witness_v0_keyhash"),
+            ("00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262", "witness_v0_scripthash"),
+            ("5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c", "witness_v1_taproot"),
+            ("51024e73", "anchor"),
+            ("6a0568656c6c6f", "nulldata"),
+            ("6aac", "nonstandard"),
+            ("51", "nonstandard"),
+        ];
+        for (script, expected) in types {
+            assert_eq!(script_type(&ScriptBuf::from_hex(script).unwrap()), expected, "{}", script);
+        }
+    }
+
+    #[test]
+    fn descriptors() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)"), "89f8spxm");
+        let descriptors = [
+            ("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac", "addr(1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa)#632p52jr"),
+            ("0014751e76e8199196d454941c45d1b3a323f1433bd6", "addr(bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4)#uyjndxcw"),
+            ("5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c", "rawtr(a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c)#h9nmpf4q"),
+            ("51210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f8179851ae", "multi(1,0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798)#gs8559k9"),
+            ("6a0568656c6c6f", "raw(6a0568656c6c6f)#yktqjuxu"),
+        ];
+        for (script, expected) in descriptors {
+            assert_eq!(script_descriptor(&ScriptBuf::from_hex(script).unwrap(), Network::Bitcoin), expected, "{}", script);
+        }
+    }
+
+    #[test]
+    fn genesis() {
+        let blocks = crate::client::tests::load_blocks();
+        let block = Block::consensus_decode(&mut blocks[0].as_slice()).unwrap();
+        let position = ChainPosition {
+            height: 0,
+            confirmations: 1000,
+            median_time: 1231006505,
+            chain_work: block.header.work(),
+            n_tx: 1,
+            next_block_hash: None,
+        };
+        let json = block_to_json(&block, &position, Network::Bitcoin, true);
+        assert_eq!(json["hash"], "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+        assert_eq!(json["versionHex"], "00000001");
+        assert_eq!(json["bits"], "1d00ffff");
+        assert_eq!(json["difficulty"], 1.0);
+        assert_eq!(json["chainwork"], "0000000000000000000000000000000000000000000000000000000100010001");
+        assert_eq!(json["previousblockhash"], Value::Null);
+        assert_eq!(json["size"], 285);
+        let tx = &json["tx"][0];
+        assert_eq!(tx["txid"], "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b");
+        assert_eq!(tx["vin"][0]["coinbase"], "04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73");
+        assert_eq!(tx["vin"][0]["sequence"], 4294967295u32);
+        assert_eq!(tx["vout"][0]["value"], 50.0);
+        assert_eq!(tx["vout"][0]["scriptPubKey"]["type"], "pubkey");
+        assert_eq!(tx["vout"][0]["scriptPubKey"]["asm"], "04678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5f OP_CHECKSIG");
+        assert_eq!(tx["vout"][0]["scriptPubKey"]["address"], Value::Null);
+        assert_eq!(tx["vout"][0]["scriptPubKey"]["desc"], "pk(04678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5f)#vlz6ztea");
+        let json = block_to_json(&block, &position, Network::Bitcoin, false);
+        assert_eq!(json["tx"][0], "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b");
+    }
+
+}
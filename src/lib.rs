@@ -6,10 +6,22 @@ use yaml_rust2::{
     YamlLoader
 };
 use bitcoin_hashes::Sha256d;
+use tracing_subscriber::EnvFilter;
 
+pub mod auth;
 pub mod blk_reader;
+pub mod block_stats;
 pub mod block_downloader;
 pub mod client;
+pub mod electrum;
+pub mod esplora;
+pub mod json;
+pub mod merkle;
+pub mod metrics;
+pub mod miner_info;
+pub mod op_return;
+pub mod rate_limiter;
+pub mod rpc;
 pub mod server;
 
 pub type Binary = Vec<u8>;
@@ -22,6 +34,20 @@ pub fn load_config() -> Yaml {
     config[0].clone()
 }
 
+/*
+ * Initialize the global tracing subscriber from the `log` section of the config.
+ * `RUST_LOG` takes precedence over `log.level`, e.g. `RUST_LOG=bitcoin_rest_mirror=debug`.
+ */
+pub fn init_logging(config: &Yaml) {
+    let level = config["log"]["level"].as_str().unwrap_or("info");
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match config["log"]["format"].as_str() {
+        Some("json") => builder.json().with_current_span(true).with_span_list(true).init(),
+        _ => builder.init(),
+    }
+}
+
 pub fn block_to_block_hash(block: &[u8]) -> [u8; 32] {
     if block.len() < 80 {
         panic!("Block is too short.");
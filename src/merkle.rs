@@ -0,0 +1,69 @@
+/*
+ * Merkle proof helpers shared by the Electrum and Esplora servers.
+ */
+
+use bitcoin::{
+    BlockHash,
+    hashes::{
+        Hash,
+        sha256d,
+    },
+};
+
+// Hash in the usual display order (i.e. reversed hex).
+pub fn hash_to_string(hash: &[u8; 32]) -> String {
+    BlockHash::from_byte_array(*hash).to_string()
+}
+
+/*
+ * Merkle branch of the leaf at `index`, as used by the Electrum and Esplora merkle proofs.
+ * Hashes are in internal byte order.
+ * @return None if `index` is out of range.
+ */
+pub fn merkle_branch(hashes: &[[u8; 32]], mut index: usize) -> Option<Vec<[u8; 32]>> {
+    if index >= hashes.len() {
+        return None;
+    }
+    let mut hashes = hashes.to_vec();
+    let mut branch = Vec::new();
+    while hashes.len() > 1 {
+        if hashes.len() % 2 == 1 {
+            hashes.push(*hashes.last().unwrap());
+        }
+        branch.push(hashes[index ^ 1]);
+        hashes = hashes.chunks(2).map(|pair| {
+            sha256d::Hash::hash(&[pair[0], pair[1]].concat()).to_byte_array()
+        }).collect();
+        index /= 2;
+    }
+    Some(branch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::client::tests::load_decoded_blocks;
+
+    #[test]
+    fn branch() {
+        let blocks = load_decoded_blocks(547);
+        let block = &blocks[546];
+        let tx_hashes = block.txdata.iter().map(|tx| tx.compute_txid().to_byte_array()).collect::<Vec<[u8; 32]>>();
+        for (pos, tx_hash) in tx_hashes.iter().enumerate() {
+            // Fold the branch back into the merkle root.
+            let mut hash = *tx_hash;
+            let mut index = pos;
+            for sibling in merkle_branch(&tx_hashes, pos).unwrap() {
+                let pair = if index % 2 == 0 { [hash, sibling] } else { [sibling, hash] };
+                hash = sha256d::Hash::hash(&pair.concat()).to_byte_array();
+                index /= 2;
+            }
+            assert_eq!(hash, block.header.merkle_root.to_byte_array());
+        }
+        assert_eq!(merkle_branch(&tx_hashes[..1], 0), Some(Vec::new()));
+        assert_eq!(merkle_branch(&tx_hashes, tx_hashes.len()), None);
+        assert_eq!(merkle_branch(&[], 0), None);
+        assert_eq!(hash_to_string(&blocks[0].block_hash().to_byte_array()), "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+    }
+}
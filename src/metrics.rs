@@ -0,0 +1,156 @@
+
+use std::sync::LazyLock;
+use std::time::{
+    Duration,
+    Instant,
+};
+use prometheus::{
+    Encoder,
+    Gauge,
+    HistogramVec,
+    IntCounter,
+    IntCounterVec,
+    IntGauge,
+    TextEncoder,
+    register_gauge,
+    register_histogram_vec,
+    register_int_counter,
+    register_int_counter_vec,
+    register_int_gauge,
+};
+use axum::{
+    Router,
+    routing::get,
+    http::{
+        StatusCode,
+        header,
+    },
+    response::IntoResponse,
+};
+
+use crate::{
+    Binary,
+    client::KVS,
+};
+
+/*
+ * Metrics are registered to the default registry of the `prometheus` crate on first use.
+ */
+
+// Server.
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| register_int_counter_vec!(
+    "bitcoin_rest_mirror_http_requests_total",
+    "Number of HTTP requests.",
+    &["route", "status"]
+).unwrap());
+pub static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| register_histogram_vec!(
+    "bitcoin_rest_mirror_http_request_duration_seconds",
+    "HTTP request latency.",
+    &["route", "status"]
+).unwrap());
+pub static KVS_OPERATION_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| register_histogram_vec!(
+    "bitcoin_rest_mirror_kvs_operation_duration_seconds",
+    "KVS operation latency.",
+    &["operation"],
+    vec![0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0]
+).unwrap());
+
+// Syncer.
+pub static SYNC_HEIGHT: LazyLock<IntGauge> = LazyLock::new(|| register_int_gauge!(
+    "bitcoin_rest_mirror_sync_height",
+    "Height of the last synced block."
+).unwrap());
+pub static SYNC_BLOCKS_PER_SECOND: LazyLock<Gauge> = LazyLock::new(|| register_gauge!(
+    "bitcoin_rest_mirror_sync_blocks_per_second",
+    "Number of blocks synced during the last second."
+).unwrap());
+pub static DOWNLOAD_QUEUE_DEPTH: LazyLock<IntGauge> = LazyLock::new(|| register_int_gauge!(
+    "bitcoin_rest_mirror_download_queue_depth",
+    "Number of downloaded blocks waiting to be processed."
+).unwrap());
+pub static BLK_READER_BUFFERED_BLOCKS: LazyLock<IntGauge> = LazyLock::new(|| register_int_gauge!(
+    "bitcoin_rest_mirror_blk_reader_buffered_blocks",
+    "Number of blocks read from blk files waiting to be processed."
+).unwrap());
+pub static FETCH_RETRIES: LazyLock<IntCounter> = LazyLock::new(|| register_int_counter!(
+    "bitcoin_rest_mirror_fetch_retries_total",
+    "Number of retried requests to the upstream REST API."
+).unwrap());
+pub static REORGS: LazyLock<IntCounter> = LazyLock::new(|| register_int_counter!(
+    "bitcoin_rest_mirror_reorgs_total",
+    "Number of blocks which replaced a different block at the same height (reorgs are not rolled back, blocks are just overwritten)."
+).unwrap());
+
+// Render all the metrics in the Prometheus text format.
+pub fn encode() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+pub async fn handle_metrics() -> impl IntoResponse {
+    (StatusCode::OK, [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], encode())
+}
+
+pub fn metrics_router() -> Router {
+    Router::new().route("/metrics", get(handle_metrics))
+}
+
+// Serve `/metrics` on a separate port, so that it is neither public nor subject to authentication and rate limiting.
+pub async fn start_metrics_server(port: u16, host: &str) {
+    let app = metrics_router();
+    let addr = format!("{}:{}", host, port);
+    let listener = tokio::net::TcpListener::bind(addr.clone()).await.unwrap();
+    tracing::info!(%addr, "Metrics server listening.");
+    axum::serve(listener, app).await.unwrap();
+}
+
+// KVS operations slower than this are logged as warnings.
+const SLOW_KVS_OPERATION: Duration = Duration::from_millis(100);
+
+// A KVS wrapper recording the latency of each operation.
+pub struct InstrumentedKVS<K: KVS> {
+    kvs: K,
+}
+
+impl<K: KVS> InstrumentedKVS<K> {
+    pub fn new(kvs: K) -> Self {
+        Self {
+            kvs,
+        }
+    }
+    fn measure<T>(operation: &str, f: impl FnOnce() -> T) -> T {
+        let start_time = Instant::now();
+        let result = f();
+        let elapsed = start_time.elapsed();
+        KVS_OPERATION_DURATION.with_label_values(&[operation]).observe(elapsed.as_secs_f64());
+        // Events are attached to the current request span, if any.
+        if elapsed >= SLOW_KVS_OPERATION {
+            tracing::warn!(operation, elapsed_ms = elapsed.as_millis() as u64, "Slow KVS operation.");
+        } else {
+            tracing::trace!(operation, elapsed_ms = elapsed.as_millis() as u64, "KVS operation.");
+        }
+        result
+    }
+}
+
+impl<K: KVS> KVS for InstrumentedKVS<K> {
+    fn get(&self, key: &str) -> Option<Binary> {
+        Self::measure("get", || self.kvs.get(key))
+    }
+    fn set(&self, key: &str, value: &[u8]) {
+        Self::measure("set", || self.kvs.set(key, value))
+    }
+    fn del(&self, key: &str) {
+        Self::measure("del", || self.kvs.del(key))
+    }
+    fn mget(&self, keys: &[String]) -> Vec<Option<Binary>> {
+        Self::measure("mget", || self.kvs.mget(keys))
+    }
+    fn incr(&self, key: &str, delta: i64) -> i64 {
+        Self::measure("incr", || self.kvs.incr(key, delta))
+    }
+    fn ping(&self) -> bool {
+        Self::measure("ping", || self.kvs.ping())
+    }
+}
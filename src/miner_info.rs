@@ -0,0 +1,118 @@
+
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use bitcoin::block::Block;
+
+// OP_RETURN OP_PUSHBYTES_36 0xaa21a9ed
+const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+// Minimum length of a printable run to be considered as a part of the coinbase tag.
+const MIN_TAG_RUN_LENGTH: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Payout {
+    pub script: String,
+    pub value: u64,
+}
+
+// Miner related information parsed from the coinbase transaction of a block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MinerInfo {
+    pub blockhash: String,
+    pub height: u32,
+    pub bip34_height: Option<u64>,
+    pub coinbase: String,
+    pub tag: String,
+    pub payouts: Vec<Payout>,
+    pub witness_commitment: Option<String>,
+}
+
+// Extract the human readable parts of the coinbase script.
+fn coinbase_tag(script_sig: &[u8]) -> String {
+    let mut runs = Vec::new();
+    let mut run = String::new();
+    for byte in script_sig.iter().chain([0u8].iter()) {
+        if (0x20..0x7f).contains(byte) {
+            run.push(*byte as char);
+            continue;
+        }
+        if run.trim().len() >= MIN_TAG_RUN_LENGTH {
+            runs.push(run.trim().to_string());
+        }
+        run.clear();
+    }
+    runs.join(" ")
+}
+
+impl MinerInfo {
+    pub fn new(height: u32, block: &Block) -> Self {
+        let coinbase = &block.txdata[0];
+        let script_sig = coinbase.input[0].script_sig.as_bytes();
+        let payouts = coinbase.output.iter()
+            .filter(|output| !output.script_pubkey.is_op_return())
+            .map(|output| Payout {
+                script: hex::encode(output.script_pubkey.as_bytes()),
+                value: output.value.to_sat(),
+            })
+            .collect();
+        // If there are multiple commitments, the one with the highest output index is used.
+        let witness_commitment = coinbase.output.iter()
+            .rev()
+            .map(|output| output.script_pubkey.as_bytes())
+            .find(|script| script.len() >= 38 && script[0..6] == WITNESS_COMMITMENT_HEADER)
+            .map(|script| hex::encode(&script[6..38]));
+        Self {
+            blockhash: block.block_hash().to_string(),
+            height,
+            bip34_height: block.bip34_block_height().ok(),
+            coinbase: hex::encode(script_sig),
+            tag: coinbase_tag(script_sig),
+            payouts,
+            witness_commitment,
+        }
+    }
+    pub fn matches_tag(&self, tag: &str) -> bool {
+        self.tag.to_lowercase().contains(&tag.to_lowercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+    use std::io::Read;
+    use bitcoin::consensus::Decodable;
+
+    #[test]
+    fn tag() {
+        assert_eq!(coinbase_tag(b"\x03\x00\x35\x0c/ViaBTC/Mined by user/\x10\xab"), "/ViaBTC/Mined by user/");
+        assert_eq!(coinbase_tag(b"\x01\x02abc\x03"), "");
+    }
+
+    #[test]
+    fn block_800_000() {
+        let mut f = File::open("./fixture/blocks/block_800000.bin").unwrap();
+        let mut block_bytes = Vec::new();
+        f.read_to_end(&mut block_bytes).unwrap();
+        let block = Block::consensus_decode(&mut block_bytes.as_slice()).unwrap();
+        let miner_info = MinerInfo::new(800_000, &block);
+        assert_eq!(miner_info.bip34_height, Some(800_000));
+        assert!(miner_info.witness_commitment.is_some());
+        assert!(!miner_info.payouts.is_empty());
+    }
+
+    #[test]
+    fn genesis() {
+        let blocks = crate::client::tests::load_blocks();
+        let block = Block::consensus_decode(&mut blocks[0].as_slice()).unwrap();
+        let miner_info = MinerInfo::new(0, &block);
+        assert_eq!(miner_info.bip34_height, None);
+        assert!(miner_info.matches_tag("the times 03/jan/2009"));
+        assert_eq!(miner_info.payouts.len(), 1);
+        assert_eq!(miner_info.payouts[0].value, 5_000_000_000);
+        assert_eq!(miner_info.witness_commitment, None);
+    }
+
+}
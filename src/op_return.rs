@@ -0,0 +1,158 @@
+
+use std::io::{
+    Read,
+    Cursor,
+};
+use serde::Serialize;
+use bitcoin::{
+    VarInt,
+    block::Block,
+    opcodes::all::OP_RETURN,
+    script::Instruction,
+    consensus::{
+        Encodable,
+        Decodable,
+    },
+};
+
+use crate::Binary;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpReturnOutput {
+    pub height: u32,
+    pub txid: [u8; 32],
+    pub vout: u32,
+    pub payload: Binary,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpReturnOutputJson {
+    pub height: u32,
+    pub txid: String,
+    pub vout: u32,
+    pub payload: String,
+}
+
+impl From<&OpReturnOutput> for OpReturnOutputJson {
+    fn from(output: &OpReturnOutput) -> Self {
+        let mut txid = output.txid;
+        txid.reverse();
+        Self {
+            height: output.height,
+            txid: hex::encode(txid),
+            vout: output.vout,
+            payload: hex::encode(&output.payload),
+        }
+    }
+}
+
+/*
+ * Extract the data carried by an OP_RETURN script.
+ * Pushed data is concatenated and any other opcode (e.g. a protocol tag like OP_13) is kept as a raw byte,
+ * so that protocols can be looked up by the leading bytes of their payload.
+ * Decoding stops at the first malformed push.
+ */
+fn script_payload(script: &bitcoin::Script) -> Binary {
+    let mut payload = Vec::new();
+    // Skip OP_RETURN itself.
+    for instruction in script.instructions().skip(1) {
+        match instruction {
+            Ok(Instruction::PushBytes(push_bytes)) => payload.extend(push_bytes.as_bytes()),
+            Ok(Instruction::Op(opcode)) => payload.push(opcode.to_u8()),
+            Err(_) => break,
+        }
+    }
+    payload
+}
+
+pub fn extract_op_return_outputs(height: u32, block: &Block) -> Vec<OpReturnOutput> {
+    let mut outputs = Vec::new();
+    for tx in block.txdata.iter() {
+        let txid: [u8; 32] = *tx.compute_txid().as_ref();
+        for (vout, output) in tx.output.iter().enumerate() {
+            if output.script_pubkey.as_bytes().first() != Some(&OP_RETURN.to_u8()) {
+                continue;
+            }
+            outputs.push(OpReturnOutput {
+                height,
+                txid,
+                vout: vout as u32,
+                payload: script_payload(&output.script_pubkey),
+            });
+        }
+    }
+    outputs
+}
+
+pub fn encode_op_return_outputs(outputs: &[OpReturnOutput]) -> Binary {
+    let mut outputs_vec = Vec::new();
+    for output in outputs {
+        outputs_vec.extend(output.txid);
+        outputs_vec.extend(output.vout.to_le_bytes());
+        VarInt::from(output.payload.len()).consensus_encode(&mut outputs_vec).unwrap();
+        outputs_vec.extend(&output.payload);
+    }
+    outputs_vec
+}
+
+pub fn decode_op_return_outputs(height: u32, outputs_vec: &[u8]) -> Vec<OpReturnOutput> {
+    let mut outputs = Vec::new();
+    let mut cursor = Cursor::new(outputs_vec);
+    while (cursor.position() as usize) < outputs_vec.len() {
+        let mut txid = [0u8; 32];
+        cursor.read_exact(&mut txid).unwrap();
+        let mut vout = [0u8; 4];
+        cursor.read_exact(&mut vout).unwrap();
+        let payload_len = VarInt::consensus_decode(&mut cursor).unwrap().0 as usize;
+        let mut payload = vec![0u8; payload_len];
+        cursor.read_exact(&mut payload).unwrap();
+        outputs.push(OpReturnOutput {
+            height,
+            txid,
+            vout: u32::from_le_bytes(vout),
+            payload,
+        });
+    }
+    outputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::ScriptBuf;
+
+    #[test]
+    fn payload() {
+        // OP_RETURN <"omni"...>
+        let script = ScriptBuf::from_hex("6a146f6d6e69000000000000001f000000002b752ee0").unwrap();
+        assert_eq!(hex::encode(script_payload(&script)), "6f6d6e69000000000000001f000000002b752ee0");
+        // OP_RETURN OP_13 <data>
+        let script = ScriptBuf::from_hex("6a5d0614c0a2331441").unwrap();
+        assert_eq!(hex::encode(script_payload(&script)), "5d14c0a2331441");
+        // OP_RETURN with a truncated push.
+        let script = ScriptBuf::from_hex("6a0201").unwrap();
+        assert_eq!(script_payload(&script), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn encode_decode() {
+        let outputs = vec![
+            OpReturnOutput {
+                height: 1,
+                txid: [1u8; 32],
+                vout: 0,
+                payload: vec![],
+            },
+            OpReturnOutput {
+                height: 1,
+                txid: [2u8; 32],
+                vout: 3,
+                payload: vec![0xaa; 300],
+            },
+        ];
+        let outputs_vec = encode_op_return_outputs(&outputs);
+        assert_eq!(decode_op_return_outputs(1, &outputs_vec), outputs);
+    }
+
+}
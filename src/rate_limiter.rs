@@ -0,0 +1,266 @@
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{
+    Duration,
+    Instant,
+};
+use yaml_rust2::Yaml;
+
+// Buckets are pruned once the number of tracked clients exceeds this.
+const PRUNE_THRESHOLD: usize = 10000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+    // Tokens refilled per second.
+    pub rate: f64,
+    // Bucket capacity, i.e. the maximum burst.
+    pub burst: f64,
+}
+
+impl Budget {
+    pub fn from_yaml(yaml: &Yaml, default: Budget) -> Self {
+        Self {
+            rate: yaml["rate"].as_f64().or(yaml["rate"].as_i64().map(|v| v as f64)).unwrap_or(default.rate),
+            burst: yaml["burst"].as_f64().or(yaml["burst"].as_i64().map(|v| v as f64)).unwrap_or(default.burst),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    // Budget of each IP address.
+    pub ip: Budget,
+    // Budget of each API key.
+    pub api_key: Budget,
+    // Additional budget consumed by expensive requests, per IP address or API key.
+    pub expensive: Budget,
+    // Path prefixes of the expensive routes.
+    pub expensive_routes: Vec<String>,
+    // `/rest/headers` requests with more headers than this are expensive.
+    pub expensive_headers_count: u32,
+    /*
+     * Identify clients by the last address of X-Forwarded-For, i.e. the one appended by the proxy in front
+     * of the server (only enable behind a trusted proxy). Preceding addresses are set by clients.
+     */
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ip: Budget { rate: 20.0, burst: 100.0 },
+            api_key: Budget { rate: 100.0, burst: 500.0 },
+            expensive: Budget { rate: 2.0, burst: 20.0 },
+            expensive_routes: vec![
+                "/rest/block/".to_string(),
+                "/rest/blockrange/".to_string(),
+                "/rest/batch/".to_string(),
+                "/rest/opreturns/".to_string(),
+                "/rest/mempool/contents.json".to_string(),
+            ],
+            expensive_headers_count: 100,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    // Load from the `rateLimit` section of a server config.
+    pub fn from_yaml(yaml: &Yaml) -> Self {
+        let default = Self::default();
+        let expensive_routes = yaml["expensiveRoutes"].as_vec().map(|routes| {
+            routes.iter().filter_map(|route| route.as_str().map(|route| route.to_string())).collect()
+        });
+        Self {
+            enabled: yaml["enabled"].as_bool().unwrap_or(default.enabled),
+            ip: Budget::from_yaml(&yaml["ip"], default.ip),
+            api_key: Budget::from_yaml(&yaml["apiKey"], default.api_key),
+            expensive: Budget::from_yaml(&yaml["expensive"], default.expensive),
+            expensive_routes: expensive_routes.unwrap_or(default.expensive_routes),
+            expensive_headers_count: yaml["expensiveHeadersCount"].as_i64().map(|v| v as u32).unwrap_or(default.expensive_headers_count),
+            trust_forwarded_for: yaml["trustForwardedFor"].as_bool().unwrap_or(default.trust_forwarded_for),
+        }
+    }
+    /*
+     * Whether a request is expensive to serve.
+     * @param query The query string without the leading `?`.
+     */
+    pub fn is_expensive(&self, path: &str, query: Option<&str>) -> bool {
+        if self.expensive_routes.iter().any(|route| path.starts_with(route.as_str())) {
+            return true;
+        }
+        let headers_path = match path.strip_prefix("/rest/headers/") {
+            Some(headers_path) => headers_path,
+            None => return false,
+        };
+        // Either `/rest/headers/<count>/<hash>` or `/rest/headers/<hash>?count=<count>`.
+        let count = match headers_path.split_once('/') {
+            Some((count, _)) => Some(count),
+            None => query.and_then(|query| query.split('&').find_map(|param| param.strip_prefix("count="))),
+        };
+        count.and_then(|count| count.parse::<u32>().ok()).is_some_and(|count| count > self.expensive_headers_count)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, budget: &Budget, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * budget.rate).min(budget.burst);
+        self.updated_at = now;
+    }
+    fn is_full(&self, budget: &Budget, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens + elapsed * budget.rate >= budget.burst
+    }
+    // Time until a token is available.
+    fn wait_time(&self, budget: &Budget) -> Duration {
+        if budget.rate <= 0.0 {
+            return Duration::MAX;
+        }
+        Duration::from_secs_f64(((1.0 - self.tokens) / budget.rate).max(0.0))
+    }
+}
+
+/*
+ * Token bucket rate limiter keyed by client.
+ * Every request consumes a token from the client's bucket, and expensive requests consume one more
+ * from a separate bucket.
+ */
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+    fn take(buckets: &mut HashMap<String, Bucket>, key: String, budget: &Budget, now: Instant) -> Result<(), Duration> {
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: budget.burst,
+            updated_at: now,
+        });
+        bucket.refill(budget, now);
+        if bucket.tokens < 1.0 {
+            return Err(bucket.wait_time(budget));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+    /*
+     * @param client Either the IP address or the API key of the client.
+     * @return The time to wait before retrying if the request is rejected.
+     */
+    pub fn check(&self, client: &str, is_api_key: bool, is_expensive: bool) -> Result<(), Duration> {
+        self.check_at(client, is_api_key, is_expensive, Instant::now())
+    }
+    fn check_at(&self, client: &str, is_api_key: bool, is_expensive: bool, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_THRESHOLD {
+            self.prune(&mut buckets, now);
+        }
+        let (kind, budget) = if is_api_key { ("key", &self.config.api_key) } else { ("ip", &self.config.ip) };
+        // Check the expensive budget first so that a rejected request does not consume the general one.
+        if is_expensive {
+            let key = format!("expensive:{}:{}", kind, client);
+            let bucket = buckets.get_mut(&key);
+            if let Some(bucket) = bucket {
+                bucket.refill(&self.config.expensive, now);
+                if bucket.tokens < 1.0 {
+                    return Err(bucket.wait_time(&self.config.expensive));
+                }
+            }
+        }
+        Self::take(&mut buckets, format!("{}:{}", kind, client), budget, now)?;
+        if is_expensive {
+            Self::take(&mut buckets, format!("expensive:{}:{}", kind, client), &self.config.expensive, now)?;
+        }
+        Ok(())
+    }
+    // Charge only the expensive budget, for requests whose general cost was already checked.
+    pub fn check_expensive(&self, client: &str, is_api_key: bool) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let kind = if is_api_key { "key" } else { "ip" };
+        Self::take(&mut buckets, format!("expensive:{}:{}", kind, client), &self.config.expensive, Instant::now())
+    }
+    // Drop the buckets which are full again, as they are equivalent to new ones.
+    fn prune(&self, buckets: &mut HashMap<String, Bucket>, now: Instant) {
+        buckets.retain(|key, bucket| {
+            let budget = if key.starts_with("expensive:") {
+                &self.config.expensive
+            } else if key.starts_with("key:") {
+                &self.config.api_key
+            } else {
+                &self.config.ip
+            };
+            !bucket.is_full(budget, now)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_rate_limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            ip: Budget { rate: 1.0, burst: 2.0 },
+            api_key: Budget { rate: 1.0, burst: 3.0 },
+            expensive: Budget { rate: 0.5, burst: 1.0 },
+            ..RateLimitConfig::default()
+        })
+    }
+
+    #[test]
+    fn token_bucket() {
+        let rate_limiter = create_rate_limiter();
+        let now = Instant::now();
+        assert!(rate_limiter.check_at("1.2.3.4", false, false, now).is_ok());
+        assert!(rate_limiter.check_at("1.2.3.4", false, false, now).is_ok());
+        assert_eq!(rate_limiter.check_at("1.2.3.4", false, false, now), Err(Duration::from_secs(1)));
+        // Other clients have their own buckets.
+        assert!(rate_limiter.check_at("5.6.7.8", false, false, now).is_ok());
+        assert!(rate_limiter.check_at("1.2.3.4", true, false, now).is_ok());
+        // Refill.
+        assert!(rate_limiter.check_at("1.2.3.4", false, false, now + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn expensive() {
+        let rate_limiter = create_rate_limiter();
+        let now = Instant::now();
+        assert!(rate_limiter.check_at("1.2.3.4", false, true, now).is_ok());
+        assert_eq!(rate_limiter.check_at("1.2.3.4", false, true, now), Err(Duration::from_secs(2)));
+        // The general budget is still available.
+        assert!(rate_limiter.check_at("1.2.3.4", false, false, now).is_ok());
+    }
+
+    #[test]
+    fn is_expensive() {
+        let config = RateLimitConfig::default();
+        assert!(config.is_expensive("/rest/block/00.bin", None));
+        assert!(!config.is_expensive("/rest/tx/00.bin", None));
+        assert!(config.is_expensive("/rest/headers/00.bin", Some("count=2000")));
+        assert!(!config.is_expensive("/rest/headers/00.bin", Some("count=5")));
+        assert!(!config.is_expensive("/rest/headers/00.bin", None));
+        assert!(config.is_expensive("/rest/headers/2000/00.bin", None));
+    }
+
+}
@@ -0,0 +1,436 @@
+/*
+ * Read-only subset of the JSON-RPC interface of Bitcoin Core, backed by the mirrored data.
+ * Both JSON-RPC 1.0 (as sent by bitcoin-cli) and 2.0 requests are supported, as well as batches.
+ * The API key may be given as the password of the HTTP Basic credentials (i.e. `bitcoin-cli -rpcpassword=<key>`).
+ */
+
+use serde_json::{
+    json,
+    Value,
+};
+use axum::http::StatusCode;
+use bitcoin::{
+    Transaction,
+    block::{
+        Block,
+        Header,
+    },
+    consensus::Decodable,
+};
+
+use crate::{
+    client::Client,
+    json::{
+        block_to_json,
+        header_to_json,
+        tx_to_json,
+    },
+    merkle::hash_to_string,
+};
+
+// Error codes of Bitcoin Core.
+pub const RPC_MISC_ERROR: i32 = -1;
+pub const RPC_TYPE_ERROR: i32 = -3;
+pub const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+pub const RPC_INVALID_PARAMETER: i32 = -8;
+pub const RPC_IN_WARMUP: i32 = -28;
+pub const RPC_INVALID_REQUEST: i32 = -32600;
+pub const RPC_METHOD_NOT_FOUND: i32 = -32601;
+pub const RPC_PARSE_ERROR: i32 = -32700;
+
+// Batches with more requests are rejected.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i32, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+    pub fn to_json(&self) -> Value {
+        json!({
+            "code": self.code,
+            "message": self.message,
+        })
+    }
+}
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn type_error(value: &Value, expected: &str) -> RpcError {
+    RpcError::new(RPC_TYPE_ERROR, &format!("JSON value of type {} is not of expected type {}", json_type_name(value), expected))
+}
+
+/*
+ * Get a parameter given either positionally or by name.
+ * Null is treated as omitted, as in Bitcoin Core.
+ */
+fn get_param<'a>(params: &'a Value, index: usize, name: &str) -> Option<&'a Value> {
+    let param = match params {
+        Value::Array(params) => params.get(index),
+        Value::Object(params) => params.get(name),
+        _ => None,
+    };
+    param.filter(|param| !param.is_null())
+}
+
+fn required_param<'a>(params: &'a Value, index: usize, name: &str) -> Result<&'a Value, RpcError> {
+    get_param(params, index, name).ok_or_else(|| RpcError::new(RPC_INVALID_PARAMETER, &format!("Missing required parameter: {}", name)))
+}
+
+// Parse a hash in the display (reversed) byte order.
+fn parse_hash(value: &Value, name: &str) -> Result<[u8; 32], RpcError> {
+    let hash_str = value.as_str().ok_or_else(|| type_error(value, "string"))?;
+    if hash_str.len() != 64 {
+        return Err(RpcError::new(RPC_INVALID_PARAMETER, &format!("{} must be of length 64 (not {}, for '{}')", name, hash_str.len(), hash_str)));
+    }
+    let mut hash = match hex::decode(hash_str) {
+        Ok(hash) => hash,
+        Err(_) => return Err(RpcError::new(RPC_INVALID_PARAMETER, &format!("{} must be hexadecimal string (not '{}')", name, hash_str))),
+    };
+    hash.reverse();
+    let mut hash_array = [0u8; 32];
+    hash_array.copy_from_slice(&hash);
+    Ok(hash_array)
+}
+
+fn parse_i64(value: &Value) -> Result<i64, RpcError> {
+    value.as_i64().ok_or_else(|| type_error(value, "number"))
+}
+
+// Verbosity given either as a bool or as a number.
+fn parse_verbosity(value: Option<&Value>, default: i64) -> Result<i64, RpcError> {
+    match value {
+        None => Ok(default),
+        Some(Value::Bool(verbose)) => Ok(*verbose as i64),
+        Some(value) => parse_i64(value),
+    }
+}
+
+fn block_not_found() -> RpcError {
+    RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Block not found")
+}
+
+// (height, block hash) of the tip.
+fn get_tip(client: &Client) -> Result<(u32, [u8; 32]), RpcError> {
+    let next_block_height = client.get_next_block_height();
+    if next_block_height == 0 {
+        return Err(RpcError::new(RPC_IN_WARMUP, "No blocks synced yet"));
+    }
+    let height = next_block_height - 1;
+    match client.get_block_hash_by_height(height) {
+        Some(block_hash) => Ok((height, block_hash)),
+        None => Err(RpcError::new(RPC_MISC_ERROR, "Tip block not found")),
+    }
+}
+
+fn getblockhash(client: &Client, params: &Value) -> Result<Value, RpcError> {
+    let height = parse_i64(required_param(params, 0, "height")?)?;
+    let next_block_height = client.get_next_block_height();
+    if height < 0 || height >= next_block_height as i64 {
+        return Err(RpcError::new(RPC_INVALID_PARAMETER, "Block height out of range"));
+    }
+    match client.get_block_hash_by_height(height as u32) {
+        Some(block_hash) => Ok(json!(hash_to_string(&block_hash))),
+        None => Err(RpcError::new(RPC_INVALID_PARAMETER, "Block height out of range")),
+    }
+}
+
+fn getblock(client: &Client, params: &Value) -> Result<Value, RpcError> {
+    let block_hash = parse_hash(required_param(params, 0, "blockhash")?, "blockhash")?;
+    let verbosity = parse_verbosity(get_param(params, 1, "verbosity"), 1)?;
+    let block = client.get_block(&block_hash).ok_or_else(block_not_found)?;
+    if verbosity <= 0 {
+        return Ok(json!(hex::encode(block)));
+    }
+    // Verbosity 3 adds the prevouts, which are not indexed.
+    if verbosity > 2 {
+        return Err(RpcError::new(RPC_INVALID_PARAMETER, "Verbosity 3 is not supported"));
+    }
+    let position = client.get_chain_position(&block_hash).ok_or_else(block_not_found)?;
+    let block = Block::consensus_decode(&mut block.as_slice()).unwrap();
+    Ok(block_to_json(&block, &position, client.get_network(), verbosity == 2))
+}
+
+fn getblockheader(client: &Client, params: &Value) -> Result<Value, RpcError> {
+    let block_hash = parse_hash(required_param(params, 0, "blockhash")?, "blockhash")?;
+    let verbose = match get_param(params, 1, "verbose") {
+        Some(Value::Bool(verbose)) => *verbose,
+        Some(value) => return Err(type_error(value, "bool")),
+        None => true,
+    };
+    let block_header = client.get_block_header(&block_hash).ok_or_else(block_not_found)?;
+    if !verbose {
+        return Ok(json!(hex::encode(block_header)));
+    }
+    let position = client.get_chain_position(&block_hash).ok_or_else(block_not_found)?;
+    let header = Header::consensus_decode(&mut block_header.as_slice()).unwrap();
+    Ok(header_to_json(&header, &position))
+}
+
+fn getrawtransaction(client: &Client, params: &Value) -> Result<Value, RpcError> {
+    let tx_hash = parse_hash(required_param(params, 0, "txid")?, "parameter 1")?;
+    let verbosity = parse_verbosity(get_param(params, 1, "verbosity"), 0)?;
+    let block_hash = match get_param(params, 2, "blockhash") {
+        Some(block_hash) => {
+            let block_hash = parse_hash(block_hash, "parameter 3")?;
+            let tx_hashes = client.get_block_transaction_hashes(&block_hash)
+                .ok_or_else(|| RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Block hash not found"))?;
+            if !tx_hashes.contains(&tx_hash) {
+                return Err(RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "No such transaction found in the provided block. Use gettransaction for wallet transactions."));
+            }
+            Some(block_hash)
+        },
+        None => client.get_block_hash_by_transaction(&tx_hash),
+    };
+    // Fall back to the mirrored mempool for unconfirmed transactions.
+    let tx = match client.get_transaction(&tx_hash).or_else(|| client.get_mempool_transaction(&tx_hash)) {
+        Some(tx) => tx,
+        None => return Err(RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "No such mempool or blockchain transaction. Use gettransaction for wallet transactions.")),
+    };
+    if verbosity <= 0 {
+        return Ok(json!(hex::encode(tx)));
+    }
+    let tx = Transaction::consensus_decode(&mut tx.as_slice()).unwrap();
+    let mut result = tx_to_json(&tx, client.get_network());
+    let position = block_hash.and_then(|block_hash| client.get_chain_position(&block_hash).map(|position| (block_hash, position)));
+    if let Some((block_hash, position)) = position {
+        let time = client.get_block_time_by_height(position.height).map(|block_time| block_time.time);
+        let result_map = result.as_object_mut().unwrap();
+        result_map.insert("blockhash".to_string(), json!(hash_to_string(&block_hash)));
+        result_map.insert("confirmations".to_string(), json!(position.confirmations));
+        result_map.insert("time".to_string(), json!(time));
+        result_map.insert("blocktime".to_string(), json!(time));
+    }
+    Ok(result)
+}
+
+fn getbestblockhash(client: &Client) -> Result<Value, RpcError> {
+    let (_, block_hash) = get_tip(client)?;
+    Ok(json!(hash_to_string(&block_hash)))
+}
+
+fn getblockcount(client: &Client) -> Result<Value, RpcError> {
+    let (height, _) = get_tip(client)?;
+    Ok(json!(height))
+}
+
+// Only the active chain is mirrored.
+fn getchaintips(client: &Client) -> Result<Value, RpcError> {
+    let (height, block_hash) = get_tip(client)?;
+    Ok(json!([{
+        "height": height,
+        "hash": hash_to_string(&block_hash),
+        "branchlen": 0,
+        "status": "active",
+    }]))
+}
+
+// Whether a call reads a whole block, i.e. should be charged to the expensive rate limit budget.
+pub fn is_expensive(request: &Value) -> bool {
+    let params = &request["params"];
+    match request["method"].as_str() {
+        Some("getblock") => parse_verbosity(get_param(params, 1, "verbosity"), 1).is_ok_and(|verbosity| verbosity >= 1),
+        Some("getrawtransaction") => get_param(params, 2, "blockhash").is_some(),
+        _ => false,
+    }
+}
+
+pub fn call(client: &Client, method: &str, params: &Value) -> Result<Value, RpcError> {
+    match method {
+        "getblockhash" => getblockhash(client, params),
+        "getblock" => getblock(client, params),
+        "getblockheader" => getblockheader(client, params),
+        "getrawtransaction" => getrawtransaction(client, params),
+        "getbestblockhash" => getbestblockhash(client),
+        "getblockcount" => getblockcount(client),
+        "getchaintips" => getchaintips(client),
+        _ => Err(RpcError::new(RPC_METHOD_NOT_FOUND, "Method not found")),
+    }
+}
+
+fn make_reply(is_v2: bool, id: &Value, result: Result<Value, RpcError>) -> Value {
+    match (is_v2, result) {
+        (true, Ok(result)) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        (true, Err(error)) => json!({ "jsonrpc": "2.0", "error": error.to_json(), "id": id }),
+        (false, Ok(result)) => json!({ "result": result, "error": null, "id": id }),
+        (false, Err(error)) => json!({ "result": null, "error": error.to_json(), "id": id }),
+    }
+}
+
+/*
+ * Handle a single request object.
+ * @return The reply and the error code if the call failed, or None for JSON-RPC 2.0 notifications.
+ */
+fn handle_request(client: &Client, request: &Value) -> Option<(Value, Option<i32>)> {
+    let request = match request.as_object() {
+        Some(request) => request,
+        None => return Some((make_reply(false, &Value::Null, Err(RpcError::new(RPC_INVALID_REQUEST, "Invalid Request object"))), Some(RPC_INVALID_REQUEST))),
+    };
+    let is_v2 = request.get("jsonrpc").and_then(|version| version.as_str()) == Some("2.0");
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let result = match (request.get("method").and_then(|method| method.as_str()), request.get("params")) {
+        (None, _) => Err(RpcError::new(RPC_INVALID_REQUEST, "Method must be a string")),
+        (Some(method), None) => call(client, method, &json!([])),
+        (Some(method), Some(params)) if params.is_array() || params.is_object() => call(client, method, params),
+        (Some(_), Some(_)) => Err(RpcError::new(RPC_INVALID_REQUEST, "Params must be an array or object")),
+    };
+    // JSON-RPC 2.0 notifications get no reply.
+    if is_v2 && !request.contains_key("id") {
+        return None;
+    }
+    let code = result.as_ref().err().map(|error| error.code);
+    Some((make_reply(is_v2, &id, result), code))
+}
+
+// Parse the body of an HTTP request, replying with a parse error if it is not JSON.
+pub fn parse_body(body: &str) -> Result<Value, (StatusCode, Value)> {
+    serde_json::from_str::<Value>(body).map_err(|_| {
+        (StatusCode::INTERNAL_SERVER_ERROR, make_reply(false, &Value::Null, Err(RpcError::new(RPC_PARSE_ERROR, "Parse error"))))
+    })
+}
+
+/*
+ * Handle the parsed body of an HTTP request, which is either a request object or a batch.
+ * HTTP status codes follow Bitcoin Core: errors of single JSON-RPC 1.0 requests are reported with
+ * 404 (method not found) or 500, oversized batches with 400, everything else with 200.
+ * @return None when there is nothing to reply (i.e. notifications only).
+ */
+pub fn handle_value(client: &Client, request: Value) -> (StatusCode, Option<Value>) {
+    if let Value::Array(requests) = request {
+        if requests.len() > MAX_BATCH_SIZE {
+            let message = format!("Batch too large: {} > {}", requests.len(), MAX_BATCH_SIZE);
+            return (StatusCode::BAD_REQUEST, Some(make_reply(false, &Value::Null, Err(RpcError::new(RPC_INVALID_REQUEST, &message)))));
+        }
+        let replies = requests.iter()
+            .filter_map(|request| handle_request(client, request))
+            .map(|(reply, _)| reply)
+            .collect::<Vec<Value>>();
+        return (StatusCode::OK, Some(Value::Array(replies)));
+    }
+    let is_v2 = request["jsonrpc"].as_str() == Some("2.0");
+    match handle_request(client, &request) {
+        None => (StatusCode::NO_CONTENT, None),
+        Some((reply, None)) => (StatusCode::OK, Some(reply)),
+        Some((reply, Some(_))) if is_v2 => (StatusCode::OK, Some(reply)),
+        Some((reply, Some(RPC_METHOD_NOT_FOUND))) => (StatusCode::NOT_FOUND, Some(reply)),
+        Some((reply, Some(_))) => (StatusCode::INTERNAL_SERVER_ERROR, Some(reply)),
+    }
+}
+
+pub fn handle_body(client: &Client, body: &str) -> (StatusCode, Option<Value>) {
+    match parse_body(body) {
+        Ok(request) => handle_value(client, request),
+        Err((status, reply)) => (status, Some(reply)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::client::tests::{
+        create_client,
+        create_synced_client,
+        load_decoded_blocks,
+    };
+
+    #[test]
+    fn chain() {
+        let client = create_synced_client(10);
+        let blocks = load_decoded_blocks(10);
+        assert_eq!(call(&client, "getblockcount", &json!([])), Ok(json!(9)));
+        assert_eq!(call(&client, "getbestblockhash", &json!([])), Ok(json!(blocks[9].block_hash().to_string())));
+        assert_eq!(call(&client, "getblockhash", &json!([3])), Ok(json!(blocks[3].block_hash().to_string())));
+        assert_eq!(call(&client, "getblockhash", &json!({ "height": 3 })), Ok(json!(blocks[3].block_hash().to_string())));
+        assert_eq!(call(&client, "getblockhash", &json!([10])).unwrap_err().code, RPC_INVALID_PARAMETER);
+        assert_eq!(call(&client, "getblockhash", &json!(["3"])).unwrap_err().code, RPC_TYPE_ERROR);
+        assert_eq!(call(&client, "getchaintips", &json!([])).unwrap()[0]["height"], 9);
+        assert_eq!(call(&create_client(), "getblockcount", &json!([])).unwrap_err().code, RPC_IN_WARMUP);
+    }
+
+    #[test]
+    fn blocks() {
+        let client = create_synced_client(10);
+        let blocks = load_decoded_blocks(10);
+        let block_hash = blocks[1].block_hash().to_string();
+        let block = call(&client, "getblock", &json!([block_hash])).unwrap();
+        assert_eq!(block["height"], 1);
+        assert_eq!(block["tx"][0], blocks[1].txdata[0].compute_txid().to_string());
+        let block = call(&client, "getblock", &json!([block_hash, 2])).unwrap();
+        assert_eq!(block["tx"][0]["txid"], blocks[1].txdata[0].compute_txid().to_string());
+        let block = call(&client, "getblock", &json!([block_hash, 0])).unwrap();
+        assert_eq!(block, json!(hex::encode(bitcoin::consensus::serialize(&blocks[1]))));
+        let header = call(&client, "getblockheader", &json!([block_hash, false])).unwrap();
+        assert_eq!(header, json!(hex::encode(bitcoin::consensus::serialize(&blocks[1].header))));
+        assert_eq!(call(&client, "getblockheader", &json!([block_hash])).unwrap()["confirmations"], 9);
+        let unknown = "00".repeat(32);
+        assert_eq!(call(&client, "getblock", &json!([unknown])).unwrap_err(), block_not_found());
+        assert_eq!(call(&client, "getblock", &json!(["zz"])).unwrap_err().code, RPC_INVALID_PARAMETER);
+    }
+
+    #[test]
+    fn transactions() {
+        let client = create_synced_client(10);
+        let blocks = load_decoded_blocks(10);
+        let tx = &blocks[2].txdata[0];
+        let txid = tx.compute_txid().to_string();
+        let raw = call(&client, "getrawtransaction", &json!([txid])).unwrap();
+        assert_eq!(raw, json!(hex::encode(bitcoin::consensus::serialize(tx))));
+        let verbose = call(&client, "getrawtransaction", &json!([txid, true])).unwrap();
+        assert_eq!(verbose["blockhash"], blocks[2].block_hash().to_string());
+        assert_eq!(verbose["confirmations"], 8);
+        assert_eq!(verbose["blocktime"], blocks[2].header.time);
+        let wrong_block = blocks[3].block_hash().to_string();
+        assert_eq!(call(&client, "getrawtransaction", &json!([txid, 1, wrong_block])).unwrap_err().code, RPC_INVALID_ADDRESS_OR_KEY);
+        assert_eq!(call(&client, "getrawtransaction", &json!(["00".repeat(32)])).unwrap_err().code, RPC_INVALID_ADDRESS_OR_KEY);
+    }
+
+    #[test]
+    fn body() {
+        let client = create_synced_client(10);
+        let blocks = load_decoded_blocks(10);
+        let (status, reply) = handle_body(&client, r#"{"jsonrpc":"1.0","id":"cli","method":"getblockcount","params":[]}"#);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(reply, Some(json!({ "result": 9, "error": null, "id": "cli" })));
+        let (status, reply) = handle_body(&client, r#"{"id":1,"method":"getblockchaininfo"}"#);
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(reply.unwrap()["error"]["code"], RPC_METHOD_NOT_FOUND);
+        let (status, reply) = handle_body(&client, r#"{"jsonrpc":"2.0","id":1,"method":"getblockhash","params":[100]}"#);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(reply, Some(json!({ "jsonrpc": "2.0", "error": { "code": RPC_INVALID_PARAMETER, "message": "Block height out of range" }, "id": 1 })));
+        let (status, reply) = handle_body(&client, r#"{"jsonrpc":"2.0","method":"getblockcount"}"#);
+        assert_eq!((status, reply), (StatusCode::NO_CONTENT, None));
+        let (status, reply) = handle_body(&client, "{");
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(reply.unwrap()["error"]["code"], RPC_PARSE_ERROR);
+        // Batch.
+        let (status, reply) = handle_body(&client, r#"[{"id":1,"method":"getblockhash","params":[0]},{"id":2,"method":"unknown"},3]"#);
+        assert_eq!(status, StatusCode::OK);
+        let reply = reply.unwrap();
+        assert_eq!(reply[0]["result"], blocks[0].block_hash().to_string());
+        assert_eq!(reply[1]["error"]["code"], RPC_METHOD_NOT_FOUND);
+        assert_eq!(reply[2]["error"]["code"], RPC_INVALID_REQUEST);
+        let batch = serde_json::to_string(&vec![json!({ "id": 1, "method": "getblockcount" }); MAX_BATCH_SIZE + 1]).unwrap();
+        let (status, reply) = handle_body(&client, &batch);
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(reply.unwrap()["error"]["code"], RPC_INVALID_REQUEST);
+    }
+
+}